@@ -1,11 +1,223 @@
-use bebe_ai::document::{self, DocumentFetcher};
+use bebe_ai::{
+    document::{
+        self,
+        chunker::ChunkerConfig,
+        corpus::{Corpus, Source, SourceMetadata},
+        crawler::{CrawlConfig, PageSource},
+        links::LinkGraph,
+        mv::GUIDE_URL,
+        snapshot::Snapshot,
+        web::SiteConfig,
+    },
+    store::{self, Store},
+};
+
+const LINKS_PATH: &str = "links.json";
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    let fetcher = document::mv::MieuxVivreFetcher::new();
-    let chunks = fetcher.fetch().await.unwrap();
-    tracing::info!("Fetched {} chunks", chunks.len());
-    let json = serde_json::to_string_pretty(&chunks).unwrap();
-    std::fs::write("chunks.json", json).unwrap();
+
+    // `crawl --record <dir>` saves every crawled page into a snapshot directory,
+    // `crawl --snapshot <dir>` crawls a saved snapshot instead of the live site.
+    // `crawl --full` ignores the previous crawl and re-chunks every page.
+    // `crawl --rps <n>` caps the crawl to n requests per second.
+    // `crawl --site <config>` crawls the site described by a TOML or JSON config instead of
+    // Mieux Vivre.
+    // `crawl --local <dir>` reads the Markdown, text and PDF files of a directory instead.
+    // `crawl --store <path>` keeps chunks and crawl state in another store than bebe.db.
+    // `crawl --import <chunks.json>` copies the chunks saved by an older crawl into the store.
+    // `crawl --export <chunks.json>` also writes every chunk of the store to a file, to `diff`
+    // with the export of another crawl.
+    // Every source is kept in the store, and only the chunks of the crawled source are replaced.
+    // The links between pages are saved to links.json.
+    let mut config = CrawlConfig::default();
+    let mut full = false;
+    let mut site = None;
+    let mut local = None;
+    let mut source = PageSource::Live;
+    let mut store_path = store::DEFAULT_PATH.to_string();
+    let mut import = None;
+    let mut export = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => source = PageSource::Record(Snapshot::new(args.next().unwrap())),
+            "--snapshot" => source = PageSource::Replay(Snapshot::new(args.next().unwrap())),
+            "--full" => full = true,
+            "--rps" => match args.next().unwrap().parse() {
+                Ok(rate) => config.requests_per_second = Some(rate),
+                Err(e) => {
+                    tracing::error!("--rps: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            "--site" => site = Some(SiteConfig::load(args.next().unwrap()).unwrap()),
+            "--local" => local = Some(args.next().unwrap()),
+            "--store" => store_path = args.next().unwrap(),
+            "--import" => import = Some(args.next().unwrap()),
+            "--export" => export = Some(args.next().unwrap()),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    let mut store = Store::open(&store_path).unwrap();
+    // Recorded with every crawl, for the manifest of the indexes embedded from it.
+    let chunker = ChunkerConfig::default();
+
+    if let Some(path) = import {
+        let corpus = Corpus::load(path).unwrap();
+        store.upsert_chunks(corpus.chunks()).unwrap();
+        tracing::info!("Imported {} chunks", corpus.len());
+        save(&store, export.as_deref());
+        return;
+    }
+
+    if let Some(dir) = local {
+        let fetcher = document::local::LocalFetcher::new(dir).with_chunker(chunker.clone());
+        let result = tokio::task::spawn_blocking(move || fetcher.read())
+            .await
+            .unwrap()
+            .unwrap();
+        tracing::info!("Read {} chunks", result.chunks.len());
+        for failure in &result.failures {
+            tracing::warn!("  {}", failure);
+        }
+
+        replace(&mut store, Source::LocalFile, &chunker, result.chunks);
+        save(&store, export.as_deref());
+        return;
+    }
+
+    if let Some(site) = site {
+        let name = site.name.clone();
+        let fetcher = document::web::WebFetcher::with_source(site, source)
+            .unwrap()
+            .with_config(config)
+            .with_chunker(chunker.clone());
+        let result = fetcher.crawl().await.unwrap();
+        tracing::info!(
+            "Fetched {} chunks from {} pages",
+            result.chunks.len(),
+            result.state.pages.len()
+        );
+        for failure in &result.failures {
+            tracing::warn!("  {}", failure);
+        }
+
+        // The state is kept for the link graph, which needs every page crawled.
+        store
+            .save_crawl_state(&Source::Web(name.clone()), &result.state)
+            .unwrap();
+        replace(&mut store, Source::Web(name), &chunker, result.chunks);
+        save(&store, export.as_deref());
+        return;
+    }
+
+    let mut fetcher = document::mv::MieuxVivreFetcher::with_source(source)
+        .with_config(config)
+        .with_chunker(chunker.clone());
+
+    if !full {
+        let state = store.crawl_state(&Source::MieuxVivre).unwrap();
+        let chunks = store.corpus().unwrap().select(|m| match m {
+            SourceMetadata::MieuxVivre(m) => Some(m),
+            _ => None,
+        });
+        tracing::info!(
+            "Previous crawl has {} pages and {} chunks",
+            state.pages.len(),
+            chunks.len()
+        );
+        fetcher = fetcher.with_previous(state, chunks);
+    }
+
+    let result = fetcher.crawl().await.unwrap();
+    tracing::info!("Fetched {} chunks", result.chunks.len());
+
+    if !result.failures.is_empty() {
+        tracing::warn!("{} pages failed:", result.failures.len());
+        for failure in &result.failures {
+            tracing::warn!("  {}", failure);
+        }
+    }
+
+    let report = &result.report;
+    tracing::info!(
+        "{} pages added, {} changed, {} removed, {} unchanged",
+        report.added.len(),
+        report.changed.len(),
+        report.removed.len(),
+        report.unchanged
+    );
+    for url in &report.added {
+        tracing::info!("  added: {}", url);
+    }
+    for url in &report.changed {
+        tracing::info!("  changed: {}", url);
+    }
+    for url in &report.removed {
+        tracing::info!("  removed: {}", url);
+    }
+
+    for (rule, stats) in &result.cleaning.rules {
+        tracing::info!(
+            "Cleaning rule {:?} altered {} and removed {} blocks",
+            rule,
+            stats.altered,
+            stats.removed
+        );
+    }
+
+    replace(&mut store, Source::MieuxVivre, &chunker, result.chunks);
+    store
+        .save_crawl_state(&Source::MieuxVivre, &result.state)
+        .unwrap();
+    save(&store, export.as_deref());
+}
+
+fn replace<M: Into<SourceMetadata>>(
+    store: &mut Store,
+    source: Source,
+    chunker: &ChunkerConfig,
+    chunks: Vec<document::Chunk<M>>,
+) {
+    let deleted = store.replace_source(&source, chunks).unwrap();
+    store.record_crawl(&source, chunker).unwrap();
+    tracing::info!(
+        "Deleted {} chunks that are gone from {}",
+        deleted,
+        source.key()
+    );
+}
+
+/// Saves the graph of links between the pages of the store, reporting the guide's pages no
+/// other page links to and its links to pages the crawl did not find, and exports the chunks
+/// of the store if asked to.
+fn save(store: &Store, export: Option<&str>) {
+    let corpus = store.corpus().unwrap();
+    if let Some(path) = export {
+        corpus.save(path).unwrap();
+        tracing::info!("Exported {} chunks to {}", corpus.len(), path);
+    }
+    let links = LinkGraph::new(store.crawled_pages().unwrap(), corpus.chunks(), |m| {
+        m.links()
+    });
+    let orphans = links
+        .orphans()
+        .into_iter()
+        .filter(|url| url.starts_with(GUIDE_URL))
+        .collect::<Vec<_>>();
+    tracing::info!(
+        "{} guide pages are not linked from any other page",
+        orphans.len()
+    );
+    for url in orphans {
+        tracing::debug!("  orphan: {}", url);
+    }
+    for (page, target) in links.broken(GUIDE_URL) {
+        tracing::warn!("Broken link from {} to {}", page, target);
+    }
+    links.save(LINKS_PATH).unwrap();
 }
@@ -0,0 +1,48 @@
+use std::{io::Read, path::Path};
+
+use bebe_ai::{
+    document::{corpus::Corpus, diff::CorpusDiff},
+    store::Store,
+};
+
+fn main() {
+    // `diff <old> <new>` reports what changed between two crawls, each given as the chunks
+    // exported by `crawl --export`, or as a copy of the store taken after the crawl.
+    // `diff --json <old> <new>` prints the same report as JSON.
+    let mut json = false;
+    let mut paths = vec![];
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if arg.starts_with("--") => panic!("Unknown argument: {}", arg),
+            _ => paths.push(arg),
+        }
+    }
+
+    let [old, new] = paths.as_slice() else {
+        panic!("Usage: diff [--json] <old chunks.json or store> <new chunks.json or store>");
+    };
+
+    let diff = CorpusDiff::new(&load(old), &load(new));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+    } else {
+        print!("{}", diff);
+    }
+}
+
+/// Loads the chunks of a store, recognized by the header of SQLite files, or of a JSON export.
+fn load(path: impl AsRef<Path>) -> Corpus {
+    let mut header = [0; 16];
+    let is_store = std::fs::File::open(&path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok()
+        && header == *b"SQLite format 3\0";
+    if is_store {
+        Store::open(path).unwrap().corpus().unwrap()
+    } else {
+        Corpus::load(path).unwrap()
+    }
+}
@@ -1,12 +1,104 @@
-use bebe_ai::document::{mv::MieuxVivreMetadata, Chunk};
+use bebe_ai::{
+    embedding::{
+        cache::CachedEmbedder,
+        config::EmbedderConfig,
+        embed_chunks,
+        index::{self, IndexManifest, Precision},
+        BatchOptions, Embedder,
+    },
+    store::{self, Store},
+};
+
+/// How many batches to embed between two saves of the cache.
+const CHECKPOINT_INTERVAL: usize = 10;
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
-    let chunks_json = std::fs::read("chunks.json").unwrap();
-    let chunks: Vec<Chunk<MieuxVivreMetadata>> = serde_json::from_slice(&chunks_json).unwrap();
-    tracing::info!("Loaded {} chunks", chunks.len());
-    let embedded = bebe_ai::embedding::get_embedded_chunks(chunks).await;
-    let json = serde_json::to_string_pretty(&embedded).unwrap();
-    std::fs::write("embedded.json", json).unwrap();
+
+    // `embed --embedder <config>` embeds with the backend described by a TOML or JSON config
+    // instead of Gemini.
+    // `embed --batch-size <n> --concurrency <n>` sends `n` chunks per batch, and embeds up to
+    // `n` batches at once.
+    // `embed --f16` stores the vectors of the index in half precision.
+    // `embed --store <path>` reads chunks from another store than bebe.db.
+    // Only the chunks of the store without a vector from the model are embedded. The vectors
+    // are saved to the store, then the index is written next to the store as
+    // index-<version>.bin, with its chunks in index-<version>.chunks.json and its manifest in
+    // index-<version>.manifest.json, and recorded in the store. Earlier versions are left as
+    // they are, for servers still using them.
+    // Vectors are also cached in embedding_cache.json, so a run that fails resumes where it stopped.
+    let mut config = EmbedderConfig::default();
+    let mut options = BatchOptions::default();
+    let mut precision = Precision::F32;
+    let mut store_path = store::DEFAULT_PATH.to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--embedder" => config = EmbedderConfig::load(args.next().unwrap()).unwrap(),
+            "--batch-size" => options.batch_size = args.next().unwrap().parse().unwrap(),
+            "--concurrency" => options.concurrency = args.next().unwrap().parse().unwrap(),
+            "--f16" => precision = Precision::F16,
+            "--store" => store_path = args.next().unwrap(),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    let mut store = Store::open(&store_path).unwrap();
+    let embedder = CachedEmbedder::load(config.build().unwrap(), "embedding_cache.json").unwrap();
+    let chunks = store.missing_embeddings(embedder.model_id()).unwrap();
+    tracing::info!("{} chunks have no vector yet", chunks.len());
+    let embedded = embed_chunks(&embedder, chunks, &options, |done, total| {
+        tracing::info!("Embedded batch {} of {}", done, total);
+        if done % CHECKPOINT_INTERVAL == 0 {
+            if let Err(e) = embedder.save() {
+                tracing::warn!("Could not save the embedding cache: {}", e);
+            }
+        }
+    })
+    .await;
+    // Whatever was embedded before a failure is kept for the next run.
+    embedder.save().unwrap();
+    let embedded = match embedded {
+        Ok(embedded) => embedded,
+        Err(e) => {
+            tracing::error!("{}; run embed again to resume", e);
+            std::process::exit(1);
+        }
+    };
+
+    store
+        .save_embeddings(embedder.model_id(), &embedded)
+        .unwrap();
+
+    let embedded = store.embedded_chunks(embedder.model_id()).unwrap();
+    let crawls = store.crawls().unwrap();
+    let manifest = IndexManifest {
+        model_id: embedder.model_id().to_string(),
+        dimension: embedder.dimension(),
+        crawled_at: crawls.values().map(|crawl| crawl.crawled_at.clone()).max(),
+        crawl_sequence: crawls.values().map(|crawl| crawl.sequence).max(),
+        chunker: crawls
+            .into_iter()
+            .map(|(source, crawl)| (source, crawl.chunker))
+            .collect(),
+        source_hash: IndexManifest::source_hash(embedded.iter().map(|e| &e.chunk)),
+    };
+    let path = store.next_index_path().unwrap();
+    index::write(&path, &manifest, &embedded, precision).unwrap();
+    let version = store
+        .record_index(
+            &path,
+            embedder.model_id(),
+            embedder.dimension(),
+            embedded.len(),
+        )
+        .unwrap();
+    tracing::info!(
+        "Wrote {} vectors to {} as index version {}",
+        embedded.len(),
+        path.display(),
+        version
+    );
 }
@@ -1,7 +1,8 @@
 use bebe_ai::{
-    document::{mv::MieuxVivreMetadata, Chunk},
-    embedding::{self, EmbeddedChunk},
+    document::corpus::SourceMetadata,
+    embedding::{config::EmbedderConfig, index::VectorIndex, Embedder},
     llm,
+    store::{self, Store},
 };
 use itertools::Itertools;
 
@@ -9,14 +10,59 @@ use itertools::Itertools;
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    // `prompt --embedder <config>` embeds the query with the backend described by a TOML or
+    // JSON config instead of Gemini. It must be the backend the index was made with.
+    // `prompt --store <path>` finds the index in another store than bebe.db.
+    // `prompt --allow-mismatch` searches an index made with another model than the embedder's,
+    // with a warning, instead of refusing to.
+    // `prompt --quantized <int8|binary>` keeps quantized copies of the vectors in memory to find
+    // candidates on, and only reads the candidates from the index to rank them.
+    // `prompt --rescore <n>` sets how many candidates per result are ranked, 4 by default.
+    let mut config = EmbedderConfig::default();
+    let mut store_path = store::DEFAULT_PATH.to_string();
+    let mut allow_mismatch = false;
+    let mut quantization = None;
+    let mut rescore_factor = 4;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--embedder" => config = EmbedderConfig::load(args.next().unwrap()).unwrap(),
+            "--store" => store_path = args.next().unwrap(),
+            "--allow-mismatch" => allow_mismatch = true,
+            "--quantized" => quantization = Some(args.next().unwrap().parse().unwrap()),
+            "--rescore" => rescore_factor = args.next().unwrap().parse().unwrap(),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
     tracing::info!("Loading embeddings from disk");
     // fetch embeddings
-    let embeddings_json = std::fs::read("embedded.json").unwrap();
-    let embeddings: Vec<
-        bebe_ai::embedding::EmbeddedChunk<bebe_ai::document::mv::MieuxVivreMetadata>,
-    > = serde_json::from_slice(&embeddings_json).unwrap();
-
-    tracing::info!("Loaded {} embeddings", embeddings.len());
+    let store = Store::open(&store_path).unwrap();
+    let version = store
+        .latest_index()
+        .unwrap()
+        .expect("No index yet, run embed first");
+    let mut index = VectorIndex::<SourceMetadata>::open(&version.path).unwrap();
+    if let Some(quantization) = quantization {
+        index = index.with_quantization(quantization, rescore_factor);
+        tracing::info!(
+            "Searching {} codes, rescoring {} per result",
+            quantization,
+            rescore_factor
+        );
+    }
+
+    tracing::info!("Loaded {} embeddings", index.len());
+
+    let embedder = config.build().unwrap();
+    if let Err(e) = index
+        .manifest()
+        .check_before_search(&embedder, &store, allow_mismatch)
+    {
+        tracing::error!("{}; pass --allow-mismatch to search it anyway", e);
+        std::process::exit(1);
+    }
 
     // prompt user for query using stdin
     let mut query = String::new();
@@ -38,57 +84,29 @@ async fn main() {
 
     tracing::info!("Generating embedding vector for serach query");
     // generate embedding for query
-    let client = reqwest::Client::new();
-    let embedding = embedding::generate_embedding(&client, &query, &gemini_key)
-        .await
-        .unwrap();
-
-    let mut similarities = embeddings
-        .iter()
-        .map(|embedded| {
-            let similarity = cosine_similarity(&embedding, &embedded.embedding);
-            (similarity, &embedded.chunk)
-        })
-        .collect::<Vec<_>>();
+    let embedding = embedder.embed_query(&query).await.unwrap();
 
     tracing::info!("Starting K Nearest Neighbors search using cosine similarity");
 
-    let sorted = similarities.as_mut_slice();
-    sorted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
-
-    // print top 5 results
-    let top5 = sorted
-        .iter()
-        .take(5)
-        .map(|(similarity, chunk)| *chunk)
-        .collect::<Vec<_>>();
+    let top5 = index.find_k_similar(&embedding, 5).unwrap();
 
     tracing::info!("Found top 5, generating context.");
 
     let context_for_prompt = top5
         .iter()
-        .map(|chunk| format!("Context from mieux vivre: {}\n\n", chunk.text))
+        .map(|chunk| chunk.metadata.context(&chunk.text))
         .collect::<String>();
 
     let prompt = format!(
-        "Using the following context from mieux vivre:\n\n{}\n\nWhat is the answer to this user query: {}",
-        context_for_prompt,
-        query
+        "Using the following context:\n\n{}\n\nWhat is the answer to this user query: {}",
+        context_for_prompt, query
     );
 
     let answer = llm::chat(&gemini_key, &prompt).await.unwrap();
 
     let context_metadata = top5
         .iter()
-        .map(|chunk| {
-            format!(
-                "Title: {}\nSection: {}\nSubsection: {}\nURL: {}\n\n",
-                chunk.metadata.title,
-                chunk.metadata.section,
-                chunk.metadata.subsection,
-                chunk.metadata.url
-            )
-        })
+        .map(|chunk| chunk.metadata.citation_text())
         .unique()
         .collect::<String>();
 
@@ -97,10 +115,3 @@ async fn main() {
     println!("\n\n");
     println!("{}", answer_with_sources);
 }
-
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f32>();
-    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
-    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
-    dot_product / (norm_a * norm_b)
-}
@@ -0,0 +1,106 @@
+use bebe_ai::{
+    document::corpus::SourceMetadata,
+    embedding::{
+        config::EmbedderConfig,
+        index::VectorIndex,
+        similarity::quantized::{Quantization, QuantizationReport},
+        EmbeddedChunk, Embedder,
+    },
+    store::{self, Store},
+};
+
+/// Questions searched when no file of queries is given.
+const DEFAULT_QUERIES: &[&str] = &[
+    "Combien de fois par jour allaiter mon bébé?",
+    "Comment coucher bébé pour dormir en sécurité?",
+    "Quand commencer les aliments solides?",
+    "Que faire quand bébé a de la fièvre?",
+    "Comment donner le bain à un nouveau-né?",
+    "Mon bébé pleure beaucoup, est-ce normal?",
+    "Quels vaccins à 2 mois?",
+    "Comment préparer un biberon de préparation commerciale?",
+    "Quand apparaissent les premières dents?",
+    "Comment choisir un siège d'auto?",
+];
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    // `quantize` reports the memory int8 and binary quantization would save on the latest
+    // index, and the recall they would lose against an exact search, for a few questions
+    // embedded as search queries.
+    // `quantize --queries <file>` searches for the questions of a text file, one per line.
+    // `quantize --embedder <config>` embeds the questions with the backend described by a TOML
+    // or JSON config instead of Gemini. It must be the backend the index was made with.
+    // `quantize --store <path>` finds the index in another store than bebe.db.
+    // `quantize --k <n>` compares the top n results, 5 by default.
+    // `quantize --rescore <n>` rescores n candidates per result at full precision, 4 by default.
+    let mut config = EmbedderConfig::default();
+    let mut store_path = store::DEFAULT_PATH.to_string();
+    let mut queries_path = None;
+    let mut k = 5;
+    let mut rescore_factor = 4;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--queries" => queries_path = Some(args.next().unwrap()),
+            "--embedder" => config = EmbedderConfig::load(args.next().unwrap()).unwrap(),
+            "--store" => store_path = args.next().unwrap(),
+            "--k" => k = args.next().unwrap().parse().unwrap(),
+            "--rescore" => rescore_factor = args.next().unwrap().parse().unwrap(),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    let questions = match queries_path {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+        None => DEFAULT_QUERIES.iter().map(|q| q.to_string()).collect(),
+    };
+
+    let version = Store::open(&store_path)
+        .unwrap()
+        .latest_index()
+        .unwrap()
+        .expect("No index yet, run embed first");
+    let index = VectorIndex::<SourceMetadata>::open(&version.path).unwrap();
+
+    let embedder = config.build().unwrap();
+    if let Err(e) = index.manifest().check(&embedder) {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+
+    let set = index
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| EmbeddedChunk {
+            embedding: index.vector(i),
+            chunk: chunk.clone(),
+        })
+        .collect::<Vec<_>>();
+    tracing::info!(
+        "Loaded {} vectors of {} dimensions",
+        set.len(),
+        index.dimension()
+    );
+
+    let mut queries = Vec::with_capacity(questions.len());
+    for question in &questions {
+        queries.push(embedder.embed_query(question).await.unwrap());
+    }
+    tracing::info!("Embedded {} queries", queries.len());
+
+    for quantization in [Quantization::Int8, Quantization::Binary] {
+        let report = QuantizationReport::new(quantization, &set, &queries, k, rescore_factor);
+        print!("{}", report);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use axum::{
     extract::{Query, State},
@@ -6,30 +6,99 @@ use axum::{
     Router,
 };
 use bebe_ai::{
-    embedding::{self, similarity::SimilarityFinder},
+    document::{corpus::SourceMetadata, Chunk},
+    embedding::{
+        cache::CachedEmbedder, config::EmbedderConfig, index::VectorIndex, AnyEmbedder, Embedder,
+    },
     llm,
+    store::{self, Store},
 };
 use itertools::Itertools;
 use tower_http::services::ServeDir;
 
+/// How often the query cache is saved, besides on shutdown.
+const CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The most query vectors to keep in the cache.
+const CACHE_LIMIT: usize = 10_000;
+
 #[derive(Debug, Clone)]
 struct AppState {
-    embeddings:
-        Arc<Vec<bebe_ai::embedding::EmbeddedChunk<bebe_ai::document::mv::MieuxVivreMetadata>>>,
-    gemini_key: String,
+    index: Arc<VectorIndex<SourceMetadata>>,
+    embedder: CachedEmbedder<AnyEmbedder>,
+    /// Without a key, the server still starts, and questions fail one by one.
+    gemini_key: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    let embeddings_json = std::fs::read("embedded.json").unwrap();
-    let embeddings: Vec<
-        bebe_ai::embedding::EmbeddedChunk<bebe_ai::document::mv::MieuxVivreMetadata>,
-    > = serde_json::from_slice(&embeddings_json).unwrap();
-    tracing::info!("Loaded {} embeddings", embeddings.len());
+    // `server --embedder <config>` embeds queries with the backend described by a TOML or JSON
+    // config instead of Gemini. It must be the backend the index was made with.
+    // `server --store <path>` finds the index in another store than bebe.db.
+    // `server --allow-mismatch` serves an index made with another model than the embedder's,
+    // with a warning, instead of refusing to.
+    // `server --quantized <int8|binary>` keeps quantized copies of the vectors in memory to find
+    // candidates on, and only reads the candidates from the index to rank them.
+    // `server --rescore <n>` sets how many candidates per result are ranked, 4 by default.
+    // Query vectors are cached in query_cache.json, so repeated queries are embedded once. The
+    // cache is saved every minute and on Ctrl-C, and keeps at most 10,000 vectors.
+    // GEMINI_API_KEY is only needed to answer questions: without it, the server starts, with
+    // another embedder than Gemini's, and answers every question with an error.
+    let mut config = EmbedderConfig::default();
+    let mut store_path = store::DEFAULT_PATH.to_string();
+    let mut allow_mismatch = false;
+    let mut quantization = None;
+    let mut rescore_factor = 4;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--embedder" => config = EmbedderConfig::load(args.next().unwrap()).unwrap(),
+            "--store" => store_path = args.next().unwrap(),
+            "--allow-mismatch" => allow_mismatch = true,
+            "--quantized" => quantization = Some(args.next().unwrap().parse().unwrap()),
+            "--rescore" => rescore_factor = args.next().unwrap().parse().unwrap(),
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
 
-    let gemini_key = std::env::var("GEMINI_API_KEY").unwrap();
+    let store = Store::open(&store_path).unwrap();
+    let version = store
+        .latest_index()
+        .unwrap()
+        .expect("No index yet, run embed first");
+    let mut index = VectorIndex::open(&version.path).unwrap();
+    if let Some(quantization) = quantization {
+        index = index.with_quantization(quantization, rescore_factor);
+        tracing::info!(
+            "Searching {} codes, rescoring {} per result",
+            quantization,
+            rescore_factor
+        );
+    }
+    tracing::info!(
+        "Using index version {} of {}",
+        version.version,
+        version.created_at
+    );
+    tracing::info!("Loaded {} embeddings", index.len());
+
+    let gemini_key = std::env::var("GEMINI_API_KEY").ok();
+    if gemini_key.is_none() {
+        tracing::warn!("GEMINI_API_KEY is not set; questions will not be answered");
+    }
+    let embedder = CachedEmbedder::load(config.build().unwrap(), "query_cache.json")
+        .unwrap()
+        .with_limit(CACHE_LIMIT);
+    if let Err(e) = index
+        .manifest()
+        .check_before_search(&embedder, &store, allow_mismatch)
+    {
+        tracing::error!("{}; pass --allow-mismatch to serve it anyway", e);
+        std::process::exit(1);
+    }
 
     let serve_dir = ServeDir::new("public");
     let app = Router::new()
@@ -37,69 +106,104 @@ async fn main() {
         .route("/chat", get(handle_chat))
         .fallback_service(serve_dir)
         .with_state(AppState {
-            embeddings: Arc::new(embeddings),
+            index: Arc::new(index),
+            embedder: embedder.clone(),
             gemini_key,
         });
 
+    let cache = embedder.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CACHE_SAVE_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            save_cache(cache.clone()).await;
+        }
+    });
+
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await
+        .unwrap();
+    save_cache(embedder).await;
+}
+
+/// Saves the query cache off the async runtime, as writing it blocks.
+async fn save_cache(embedder: CachedEmbedder<AnyEmbedder>) {
+    match tokio::task::spawn_blocking(move || embedder.save()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::warn!("Could not save the query cache: {}", e),
+        Err(e) => tracing::warn!("Could not save the query cache: {}", e),
+    }
 }
 
 async fn handle_chat(
     Query(params): Query<HashMap<String, String>>,
     State(state): State<AppState>,
 ) -> String {
-    tracing::info!("Generating search query from user query");
+    let Some(query) = params.get("query") else {
+        return "The query parameter is missing".to_string();
+    };
+
+    let Some(gemini_key) = &state.gemini_key else {
+        tracing::error!("Cannot answer without GEMINI_API_KEY");
+        return "GEMINI_API_KEY is not set on the server".to_string();
+    };
 
-    let query = params.get("query").unwrap();
+    tracing::info!("Generating search query from user query");
 
     tracing::info!("User query: {}", query);
 
     // convert user query to search query
     let query = query.trim().to_string();
-    let query = llm::chat(&state.gemini_key, &format!(
+    let query = match llm::chat(gemini_key, &format!(
         "Convert the following user query to a search query: {}. Only respond with the search query, nothing else.", query
-    )).await.unwrap();
+    )).await {
+        Ok(query) => query,
+        Err(e) => {
+            tracing::error!("Could not generate a search query: {}", e);
+            return format!("Could not generate a search query: {}", e);
+        }
+    };
 
     tracing::info!("Using search query: {}", query);
 
-    tracing::info!("Generating embedding vector for serach query");
-    // generate embedding for query
-    let client = reqwest::Client::new();
-    let embedding = embedding::generate_embedding(&client, &query, &state.gemini_key)
-        .await
-        .unwrap();
-
-    let similarity = embedding::similarity::naive::NaiveSimilarity {};
-    let top5 = similarity.find_k_similar(&embedding, state.embeddings.as_ref(), 5);
+    let top5 = match search(&state, &query).await {
+        Ok(top5) => top5,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return e;
+        }
+    };
 
     tracing::info!("Found top 5, generating context.");
 
     let context_for_prompt = top5
         .iter()
-        .map(|chunk| format!("Context from mieux vivre: {}\n\n", chunk.chunk.text))
+        .map(|chunk| chunk.metadata.context(&chunk.text))
         .collect::<String>();
 
     let prompt = format!(
-        "Using the following context from mieux vivre:\n\n{}\n\nWhat is the answer to this user query: {}. Please quote the mieux vivre context in your answer when possible.",
+        "Using the following context:\n\n{}\n\nWhat is the answer to this user query: {}. Please quote the context in your answer when possible.",
         context_for_prompt,
         query
     );
 
-    let answer = llm::chat(&state.gemini_key, &prompt).await.unwrap();
+    let answer = match llm::chat(gemini_key, &prompt).await {
+        Ok(answer) => answer,
+        Err(e) => {
+            tracing::error!("Could not generate an answer: {}", e);
+            return format!("Could not generate an answer: {}", e);
+        }
+    };
 
     let context_metadata = top5
         .iter()
-        .map(|chunk| {
-            format!(
-                "Titre: {}\nSection: {}\nSous-section: {}\nURL: {}\n\n",
-                chunk.chunk.metadata.title,
-                chunk.chunk.metadata.section,
-                chunk.chunk.metadata.subsection,
-                chunk.chunk.metadata.url
-            )
-        })
+        .map(|chunk| chunk.metadata.citation_text())
         .unique()
         .collect::<String>();
 
@@ -107,3 +211,154 @@ async fn handle_chat(
 
     answer_with_sources
 }
+
+/// The five chunks of the index closest to a search query.
+async fn search<'a>(
+    state: &'a AppState,
+    query: &str,
+) -> Result<Vec<&'a Chunk<SourceMetadata>>, String> {
+    tracing::info!("Generating embedding vector for serach query");
+    // generate embedding for query
+    let embedding = state
+        .embedder
+        .embed_query(query)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Starting K Nearest Neighbors search using cosine similarity");
+    state
+        .index
+        .find_k_similar(&embedding, 5)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::extract::{Query, State};
+    use bebe_ai::{
+        document::{chunker::ChunkerConfig, corpus::Source, mv, snapshot::Snapshot},
+        embedding::{
+            cache::CachedEmbedder,
+            config::EmbedderConfig,
+            embed_chunks,
+            hashing::HashingConfig,
+            index::{self, IndexManifest, Precision, VectorIndex},
+            BatchOptions, Embedder,
+        },
+        store::Store,
+    };
+
+    use super::{handle_chat, search, AppState};
+
+    const SECTION_URL: &str = "https://www.inspq.qc.ca/mieux-vivre/grossesse";
+    const PAGES: [(&str, &str); 2] = [
+        (
+            "https://www.inspq.qc.ca/mieux-vivre/grossesse/nausees",
+            "<h1>Les nausées</h1><div class=\"two-column-layout__left\"><div class=\"field__item\">
+            <p>Les nausées du matin sont fréquentes au premier trimestre de la grossesse.</p>
+            </div></div>",
+        ),
+        (
+            "https://www.inspq.qc.ca/mieux-vivre/grossesse/sommeil",
+            "<h1>Le sommeil</h1><div class=\"two-column-layout__left\"><div class=\"field__item\">
+            <p>Couchez toujours votre bébé sur le dos dans son propre lit.</p>
+            </div></div>",
+        ),
+    ];
+
+    /// Crawls a snapshot of the guide into a store, embeds it with the hashing backend and
+    /// serves its index, all offline.
+    #[tokio::test]
+    async fn test_search_crawled_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot::new(dir.path().join("snapshot"));
+        snapshot
+            .write(
+                mv::ROOT,
+                "<div class=\"carte-lien-mv\"><a href=\"/mieux-vivre/grossesse\"><img src=\"g.jpg\"><span>Grossesse</span></a></div>",
+            )
+            .await
+            .unwrap();
+        let menu = PAGES
+            .iter()
+            .map(|(url, _)| format!("<li><a href=\"{}\">Page</a></li>", url))
+            .collect::<String>();
+        snapshot
+            .write(
+                SECTION_URL,
+                &format!(
+                    "<nav id=\"block-mieuxvivre-post-content-menu\"><ul class=\"menu\">{}</ul></nav>",
+                    menu
+                ),
+            )
+            .await
+            .unwrap();
+        for (url, html) in PAGES {
+            snapshot.write(url, html).await.unwrap();
+        }
+
+        let result = mv::MieuxVivreFetcher::from_snapshot(snapshot.dir())
+            .crawl()
+            .await
+            .unwrap();
+        assert!(result.failures.is_empty(), "{:?}", result.failures);
+        let mut store = Store::open(dir.path().join("bebe.db")).unwrap();
+        store
+            .replace_source(&Source::MieuxVivre, result.chunks)
+            .unwrap();
+        store
+            .record_crawl(&Source::MieuxVivre, &ChunkerConfig::default())
+            .unwrap();
+
+        let embedder = EmbedderConfig::Hashing(HashingConfig::default())
+            .build()
+            .unwrap();
+        let chunks = store.missing_embeddings(embedder.model_id()).unwrap();
+        let embedded = embed_chunks(&embedder, chunks, &BatchOptions::default(), |_, _| {})
+            .await
+            .unwrap();
+        store
+            .save_embeddings(embedder.model_id(), &embedded)
+            .unwrap();
+        let embedded = store.embedded_chunks(embedder.model_id()).unwrap();
+        let manifest = IndexManifest {
+            model_id: embedder.model_id().to_string(),
+            dimension: embedder.dimension(),
+            crawled_at: store.last_crawl().unwrap().map(|crawl| crawl.crawled_at),
+            crawl_sequence: store.last_crawl().unwrap().map(|crawl| crawl.sequence),
+            chunker: store
+                .crawls()
+                .unwrap()
+                .into_iter()
+                .map(|(source, crawl)| (source, crawl.chunker))
+                .collect(),
+            source_hash: IndexManifest::source_hash(embedded.iter().map(|e| &e.chunk)),
+        };
+        let path = dir.path().join("index.bin");
+        index::write(&path, &manifest, &embedded, Precision::F32).unwrap();
+
+        let index = VectorIndex::open(&path).unwrap();
+        index.manifest().check(&embedder).unwrap();
+        assert!(index.manifest().changes_since(&store).unwrap().is_empty());
+        let state = AppState {
+            index: Arc::new(index),
+            embedder: CachedEmbedder::load(embedder, dir.path().join("query_cache.json")).unwrap(),
+            gemini_key: None,
+        };
+
+        let top = search(&state, "nausées du matin").await.unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].metadata.location(), PAGES[0].0);
+
+        // Without a Gemini key, questions fail without taking the server down.
+        let query = HashMap::from([("query".to_string(), "Nausées ?".to_string())]);
+        let answer = handle_chat(Query(query), State(state.clone())).await;
+        assert!(answer.contains("GEMINI_API_KEY"));
+
+        // Nor do requests without a query.
+        let answer = handle_chat(Query(HashMap::new()), State(state)).await;
+        assert!(answer.contains("query parameter is missing"));
+    }
+}
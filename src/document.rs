@@ -1,31 +1,121 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
+pub mod block;
+pub mod chunker;
+pub mod clean;
+pub mod corpus;
+pub mod crawler;
+pub mod diff;
+pub mod error;
+pub mod links;
+pub mod local;
 pub mod mv;
+pub mod robots;
+pub mod snapshot;
+pub mod state;
+pub mod table;
+pub mod web;
 
 pub trait DocumentFetcher<M> {
     #[allow(async_fn_in_trait)]
     async fn fetch(&self) -> Result<Vec<Chunk<M>>, Box<dyn std::error::Error>>;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Metadata that locates a chunk within its source, from which chunk IDs are derived.
+pub trait ChunkMetadata {
+    fn url(&self) -> &str;
+    fn heading_path(&self) -> String;
+
+    /// The title of the page or file the chunk comes from, if it has one.
+    fn title(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Chunk<M> {
+    /// A stable identifier, see [`Chunk::assign_ids`]. Empty until assigned, and in chunks
+    /// saved before IDs existed.
+    #[serde(default)]
+    pub id: String,
     pub text: String,
     pub metadata: M,
 }
 
+impl<M> Chunk<M> {
+    /// A chunk without an ID yet.
+    pub fn new(text: impl Into<String>, metadata: M) -> Self {
+        Self {
+            id: String::new(),
+            text: text.into(),
+            metadata,
+        }
+    }
+}
+
+impl<M: ChunkMetadata> Chunk<M> {
+    /// Gives every chunk an ID derived from its URL, heading path and whitespace and Unicode
+    /// normalized text, so the same content keeps the same ID across crawls whatever its
+    /// position. Identical chunks get a `-2`, `-3`… suffix in order of appearance.
+    pub fn assign_ids(chunks: &mut [Chunk<M>]) {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for chunk in chunks {
+            let id = chunk.content_id();
+            let count = seen.entry(id.clone()).or_default();
+            *count += 1;
+            chunk.id = match count {
+                1 => id,
+                n => format!("{}-{}", id, n),
+            };
+        }
+    }
+
+    fn content_id(&self) -> String {
+        let text = self
+            .text
+            .nfc()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let key = format!(
+            "{}\n{}\n{}",
+            self.metadata.url(),
+            self.metadata.heading_path(),
+            text
+        );
+        state::content_hash(&key)[..16].to_string()
+    }
+}
+
+/// The outcome of a crawl that tolerates per-page failures.
+#[derive(Debug)]
+pub struct CrawlResult<M> {
+    /// Chunks from every page that was crawled successfully.
+    pub chunks: Vec<Chunk<M>>,
+    /// Pages that could not be crawled, and why.
+    pub failures: Vec<error::CrawlError>,
+    /// What to remember about every page for the next incremental crawl.
+    pub state: state::CrawlState,
+    /// The pages that changed since the previous crawl.
+    pub report: state::CrawlReport,
+    /// What text cleaning did to the chunks of the pages crawled.
+    pub cleaning: clean::CleanReport,
+}
+
 // tests
 #[cfg(test)]
 mod tests {
-    use super::{Chunk, DocumentFetcher};
+    use super::{Chunk, ChunkMetadata, DocumentFetcher};
 
     struct SimpleFetcher;
 
     impl DocumentFetcher<()> for SimpleFetcher {
         async fn fetch(&self) -> Result<Vec<Chunk<()>>, Box<dyn std::error::Error>> {
-            Ok(vec![Chunk {
-                text: "Hello, world!".to_string(),
-                metadata: (),
-            }])
+            Ok(vec![Chunk::new("Hello, world!", ())])
         }
     }
 
@@ -36,4 +126,38 @@ mod tests {
         assert_eq!(documents.len(), 1);
         assert_eq!(documents[0].text, "Hello, world!");
     }
+
+    struct Page(&'static str, &'static str);
+
+    impl ChunkMetadata for Page {
+        fn url(&self) -> &str {
+            self.0
+        }
+
+        fn heading_path(&self) -> String {
+            self.1.to_string()
+        }
+    }
+
+    #[test]
+    fn test_assign_ids() {
+        let mut chunks = vec![
+            Chunk::new("Le bébé dort.", Page("https://a", "Sommeil")),
+            Chunk::new("Le bébé dort.", Page("https://b", "Sommeil")),
+            Chunk::new("Le bébé dort.", Page("https://a", "Repas")),
+            Chunk::new("Le  bébé\ndort.", Page("https://a", "Sommeil")),
+        ];
+        Chunk::assign_ids(&mut chunks);
+
+        let ids = chunks.iter().map(|c| c.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids[0].len(), 16);
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        assert_eq!(ids[3], format!("{}-2", ids[0]));
+
+        // IDs do not depend on position.
+        let mut alone = vec![Chunk::new("Le bébé dort.", Page("https://b", "Sommeil"))];
+        Chunk::assign_ids(&mut alone);
+        assert_eq!(alone[0].id, ids[1]);
+    }
 }
@@ -0,0 +1,219 @@
+use scraper::{ElementRef, Selector};
+use serde::{Deserialize, Serialize};
+
+use super::chunker::count_tokens;
+
+/// Boxes larger than this are only callouts when their class says so: a whole section that
+/// happens to start with "Important" is not a warning.
+const MAX_PREFIXED_CALLOUT_TOKENS: usize = 150;
+
+/// What kind of content a chunk holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockType {
+    /// Paragraphs and lists.
+    #[default]
+    Text,
+    Table,
+    /// An image's alt text and caption.
+    Figure,
+    /// A box set apart from the text, such as a tip or a note.
+    Callout,
+    /// A box warning about a risk, such as "Important" or "Mise en garde".
+    Warning,
+    /// A box summarizing what to remember, such as "À retenir".
+    Summary,
+}
+
+impl BlockType {
+    /// How to label the block when quoting it, or `None` for plain text.
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            BlockType::Text => None,
+            BlockType::Table => Some("Tableau"),
+            BlockType::Figure => Some("Figure"),
+            BlockType::Callout => Some("Encadré"),
+            BlockType::Warning => Some("Mise en garde"),
+            BlockType::Summary => Some("À retenir"),
+        }
+    }
+
+    /// Recognizes callout boxes from their class names, or, for small boxes holding no other
+    /// boxes, from the words they start with.
+    ///
+    /// Class names are split into words at `-` and `_` and matched word for word, so
+    /// `encadre--a-retenir` is a summary but `notification` is not a note.
+    pub fn of_callout(element: ElementRef) -> Option<Self> {
+        let classes = element
+            .value()
+            .classes()
+            .map(|class| {
+                class
+                    .to_lowercase()
+                    .split(['-', '_'])
+                    .filter(|word| !word.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let has_class = |terms: &[&str]| {
+            terms.iter().any(|term| {
+                let term = term.split('-').collect::<Vec<_>>();
+                classes
+                    .iter()
+                    .any(|words| words.windows(term.len()).any(|window| window == term))
+            })
+        };
+
+        let leaf_selector = Selector::parse("div, section, article, aside, table").unwrap();
+        let text = element.text().collect::<String>();
+        let small_leaf = element.select(&leaf_selector).next().is_none()
+            && count_tokens(&text) <= MAX_PREFIXED_CALLOUT_TOKENS;
+        let start = text
+            .split_whitespace()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        let starts_with =
+            |words: &[&str]| small_leaf && words.iter().any(|word| start.starts_with(word));
+
+        const SUMMARY: [&str; 3] = ["retenir", "resume", "summary"];
+        const WARNING: [&str; 6] = [
+            "important",
+            "attention",
+            "avertissement",
+            "mise-en-garde",
+            "warning",
+            "danger",
+        ];
+        const CALLOUT: [&str; 5] = ["encadre", "callout", "note", "astuce", "info"];
+
+        if has_class(&SUMMARY) || starts_with(&["à retenir", "a retenir"]) {
+            Some(BlockType::Summary)
+        } else if has_class(&WARNING)
+            || starts_with(&["important", "attention", "mise en garde", "avertissement"])
+        {
+            Some(BlockType::Warning)
+        } else if has_class(&CALLOUT) {
+            Some(BlockType::Callout)
+        } else {
+            None
+        }
+    }
+}
+
+/// An image, with what it shows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Figure {
+    pub src: Option<String>,
+    pub alt: Option<String>,
+    pub caption: Option<String>,
+}
+
+impl Figure {
+    /// Parses a `figure` or `img` element. Returns `None` for decorative images, which have
+    /// neither alt text nor a caption.
+    pub fn parse(element: ElementRef) -> Option<Self> {
+        let img_selector = Selector::parse("img").unwrap();
+        let caption_selector = Selector::parse("figcaption").unwrap();
+
+        let img = if element.value().name() == "img" {
+            Some(element)
+        } else {
+            element.select(&img_selector).next()
+        };
+        let non_empty = |text: String| {
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!text.is_empty()).then_some(text)
+        };
+
+        let figure = Figure {
+            src: img
+                .and_then(|img| img.value().attr("src"))
+                .map(String::from),
+            alt: img
+                .and_then(|img| img.value().attr("alt"))
+                .and_then(|alt| non_empty(alt.to_string())),
+            caption: element
+                .select(&caption_selector)
+                .next()
+                .and_then(|caption| non_empty(caption.text().collect())),
+        };
+
+        (figure.alt.is_some() || figure.caption.is_some()).then_some(figure)
+    }
+
+    /// The text to embed for the figure: its caption, then its alt text.
+    pub fn to_text(&self) -> String {
+        self.caption
+            .iter()
+            .map(|caption| format!("Légende : {}", caption))
+            .chain(self.alt.iter().map(|alt| format!("Image : {}", alt)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::{Html, Selector};
+
+    use super::{BlockType, Figure};
+
+    fn first<'a>(html: &'a Html, selector: &str) -> scraper::ElementRef<'a> {
+        html.select(&Selector::parse(selector).unwrap())
+            .next()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_callouts() {
+        let html = Html::parse_fragment(
+            r#"<div id="a" class="encadre encadre--a-retenir"><h3>Bon à savoir</h3></div>
+            <div id="b"><strong>Mise en garde :</strong> ne secouez jamais un bébé.</div>
+            <div id="c" class="encadre"><p>Astuce pour le bain.</p></div>
+            <div id="d"><p>Un paragraphe.</p></div>
+            <div id="e" class="notification-bar"><p>Nouveau contenu.</p></div>
+            <div id="f"><p>Important : voici la suite.</p><div><p>Le sommeil.</p></div></div>"#,
+        );
+
+        let types = ["#a", "#b", "#c", "#d", "#e", "#f"]
+            .map(|selector| BlockType::of_callout(first(&html, selector)));
+        assert_eq!(
+            types,
+            [
+                Some(BlockType::Summary),
+                Some(BlockType::Warning),
+                Some(BlockType::Callout),
+                None,
+                None,
+                None
+            ]
+        );
+
+        let long = format!(
+            "<div><p>Attention : {}</p></div>",
+            "le bébé dort. ".repeat(100)
+        );
+        let html = Html::parse_fragment(&long);
+        assert_eq!(BlockType::of_callout(first(&html, "div")), None);
+    }
+
+    #[test]
+    fn test_figure() {
+        let html = Html::parse_fragment(
+            r#"<figure><img src="/bain.jpg" alt="Un parent donne le bain à son bébé">
+            <figcaption>Le bain  du nouveau-né</figcaption></figure>
+            <img id="deco" src="/ligne.png" alt="">"#,
+        );
+
+        let figure = Figure::parse(first(&html, "figure")).unwrap();
+        assert_eq!(figure.src.as_deref(), Some("/bain.jpg"));
+        assert_eq!(
+            figure.to_text(),
+            "Légende : Le bain du nouveau-né\nImage : Un parent donne le bain à son bébé"
+        );
+        assert_eq!(Figure::parse(first(&html, "#deco")), None);
+    }
+}
@@ -0,0 +1,418 @@
+use serde::{Deserialize, Serialize};
+
+use super::Chunk;
+
+/// Sizes are counted in approximate tokens, see [`count_tokens`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkerConfig {
+    /// Consecutive blocks with the same metadata are merged up to this size.
+    pub target_tokens: usize,
+    /// Blocks larger than this are split at sentence boundaries into `target_tokens` pieces.
+    pub max_tokens: usize,
+    /// How much of the end of a chunk is repeated at the start of the next one, cut so that
+    /// the next one stays within `max_tokens`.
+    pub overlap_tokens: usize,
+    /// Chunks still smaller than this after merging are merged into a neighbour with the same
+    /// metadata, up to `max_tokens`, or kept on their own when there is none.
+    pub min_tokens: usize,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            target_tokens: 256,
+            max_tokens: 384,
+            overlap_tokens: 32,
+            min_tokens: 4,
+        }
+    }
+}
+
+/// Turns parsed blocks (paragraphs, lists, callouts) into evenly sized chunks.
+///
+/// Blocks are only ever merged with, or overlapped onto, neighbours that have the same
+/// metadata, so every resulting chunk keeps metadata that is accurate for all of its text.
+#[derive(Debug, Clone, Default)]
+pub struct Chunker {
+    config: ChunkerConfig,
+}
+
+/// Approximates the number of tokens in a text, as roughly four characters per token and
+/// never fewer than one token per word.
+pub fn count_tokens(text: &str) -> usize {
+    let words = text.split_whitespace().count();
+    let chars = text.chars().filter(|c| !c.is_whitespace()).count();
+    words.max(chars.div_ceil(4))
+}
+
+impl Chunker {
+    pub fn new(config: ChunkerConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &ChunkerConfig {
+        &self.config
+    }
+
+    pub fn chunk<M: Clone + PartialEq>(&self, blocks: Vec<Chunk<M>>) -> Vec<Chunk<M>> {
+        self.chunk_with_sources(blocks)
+            .into_iter()
+            .map(|(chunk, _)| chunk)
+            .collect()
+    }
+
+    /// Like [`Chunker::chunk`], with the indices of the blocks each chunk was made from, so
+    /// what is known of a block but left out of the metadata can follow it into its chunks.
+    pub fn chunk_with_sources<M: Clone + PartialEq>(
+        &self,
+        blocks: Vec<Chunk<M>>,
+    ) -> Vec<(Chunk<M>, Vec<usize>)> {
+        let mut pieces: Vec<(Chunk<M>, usize)> = vec![];
+        for (i, block) in blocks.into_iter().enumerate() {
+            let text = block.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            if count_tokens(text) <= self.config.max_tokens {
+                pieces.push((Chunk::new(text, block.metadata), i));
+            } else {
+                pieces.extend(
+                    self.split(text)
+                        .into_iter()
+                        .map(|text| (Chunk::new(text, block.metadata.clone()), i)),
+                );
+            }
+        }
+
+        let mut chunks: Vec<(Chunk<M>, Vec<usize>)> = vec![];
+        for (piece, source) in pieces {
+            if let Some((last, sources)) = chunks.last_mut() {
+                if last.metadata == piece.metadata
+                    && count_tokens(&last.text) + count_tokens(&piece.text)
+                        <= self.config.target_tokens
+                {
+                    append(last, sources, piece, vec![source]);
+                    continue;
+                }
+            }
+            chunks.push((piece, vec![source]));
+        }
+
+        self.merge_short(&mut chunks);
+
+        if self.config.overlap_tokens > 0 {
+            // Going backwards, every previous chunk is still free of overlap.
+            for i in (1..chunks.len()).rev() {
+                if chunks[i - 1].0.metadata != chunks[i].0.metadata {
+                    continue;
+                }
+                let room = self
+                    .config
+                    .max_tokens
+                    .saturating_sub(count_tokens(&chunks[i].0.text));
+                let overlap = tail(&chunks[i - 1].0.text, self.config.overlap_tokens.min(room));
+                if !overlap.is_empty() {
+                    chunks[i].0.text = format!("{} {}", overlap, chunks[i].0.text);
+                }
+            }
+        }
+
+        chunks
+    }
+
+    /// Merges chunks under `min_tokens` into the previous or next chunk with the same
+    /// metadata, if the result fits in `max_tokens`. A short text under a heading of its own,
+    /// such as a warning, is kept as it is.
+    fn merge_short<M: PartialEq>(&self, chunks: &mut Vec<(Chunk<M>, Vec<usize>)>) {
+        let mut i = 0;
+        while i < chunks.len() {
+            let tokens = count_tokens(&chunks[i].0.text);
+            if tokens >= self.config.min_tokens {
+                i += 1;
+                continue;
+            }
+
+            let fits = |other: &Chunk<M>, chunk: &Chunk<M>| {
+                other.metadata == chunk.metadata
+                    && count_tokens(&other.text) + tokens <= self.config.max_tokens
+            };
+            if i > 0 && fits(&chunks[i - 1].0, &chunks[i].0) {
+                let (short, sources) = chunks.remove(i);
+                let (previous, previous_sources) = &mut chunks[i - 1];
+                append(previous, previous_sources, short, sources);
+            } else if i + 1 < chunks.len() && fits(&chunks[i + 1].0, &chunks[i].0) {
+                let (next, next_sources) = chunks.remove(i + 1);
+                let (short, sources) = &mut chunks[i];
+                append(short, sources, next, next_sources);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Splits an oversized text into pieces of at most `target_tokens`, at sentence
+    /// boundaries when possible, and at word boundaries for oversized sentences.
+    fn split(&self, text: &str) -> Vec<String> {
+        let target = self.config.target_tokens.max(1);
+        let mut pieces = vec![];
+        let mut current = String::new();
+
+        let mut push = |current: &mut String, part: &str| {
+            if !current.is_empty() && count_tokens(current) + count_tokens(part) > target {
+                pieces.push(std::mem::take(current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(part);
+        };
+
+        for sentence in split_sentences(text) {
+            if count_tokens(sentence) <= target {
+                push(&mut current, sentence);
+                continue;
+            }
+
+            for word in sentence.split_whitespace() {
+                push(&mut current, word);
+            }
+        }
+
+        if !current.is_empty() {
+            pieces.push(current);
+        }
+
+        pieces
+    }
+}
+
+/// Appends a chunk with the same metadata to another, on a new line.
+fn append<M>(chunk: &mut Chunk<M>, sources: &mut Vec<usize>, other: Chunk<M>, others: Vec<usize>) {
+    chunk.text.push('\n');
+    chunk.text.push_str(&other.text);
+    for source in others {
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+}
+
+/// Splits a text into sentences: after `.`, `!`, `?` or `…` (and any closing quote or
+/// parenthesis) followed by whitespace and a capital letter, digit or opening quote, and
+/// at line breaks.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let chars = text.char_indices().collect::<Vec<_>>();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (index, c) = chars[i];
+
+        if c == '\n' {
+            sentences.push(&text[start..index]);
+            start = index + 1;
+        } else if matches!(c, '.' | '!' | '?' | '…') {
+            let mut end = i + 1;
+            while end < chars.len() && matches!(chars[end].1, '»' | '"' | '\'' | ')' | '’') {
+                end += 1;
+            }
+            let mut next = end;
+            while next < chars.len() && chars[next].1.is_whitespace() && chars[next].1 != '\n' {
+                next += 1;
+            }
+
+            let starts_sentence = chars
+                .get(next)
+                .is_some_and(|(_, c)| c.is_uppercase() || c.is_numeric() || *c == '«');
+            if next > end && starts_sentence {
+                let end_index = chars.get(end).map_or(text.len(), |(index, _)| *index);
+                sentences.push(&text[start..end_index]);
+                start = chars[next].0;
+                i = next;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    sentences.push(&text[start..]);
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The end of a text worth at most `tokens`: whole trailing sentences, or trailing words
+/// when even the last sentence is too long.
+fn tail(text: &str, tokens: usize) -> String {
+    let sentences = split_sentences(text);
+
+    let mut taken = 0;
+    let mut count = 0;
+    for sentence in sentences.iter().rev() {
+        count += count_tokens(sentence);
+        if count > tokens {
+            break;
+        }
+        taken += 1;
+    }
+
+    if taken > 0 {
+        return sentences[sentences.len() - taken..].join(" ");
+    }
+
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let mut start = words.len();
+    let mut count = 0;
+    while start > 0 {
+        count += count_tokens(words[start - 1]);
+        if count > tokens {
+            break;
+        }
+        start -= 1;
+    }
+    words[start..].join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_tokens, split_sentences, Chunker, ChunkerConfig};
+    use crate::document::Chunk;
+
+    fn chunk(text: &str, heading: &str) -> Chunk<String> {
+        Chunk::new(text, heading.to_string())
+    }
+
+    fn chunker(target_tokens: usize, overlap_tokens: usize) -> Chunker {
+        Chunker::new(ChunkerConfig {
+            target_tokens,
+            max_tokens: target_tokens,
+            overlap_tokens,
+            min_tokens: 1,
+        })
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            split_sentences(
+                "Le bébé dort. Il a 3 mois! Est-ce normal? «Oui.» Voir p. ex. la section.\nFin"
+            ),
+            vec![
+                "Le bébé dort.",
+                "Il a 3 mois!",
+                "Est-ce normal?",
+                "«Oui.»",
+                "Voir p. ex. la section.",
+                "Fin"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merges_blocks_with_same_metadata() {
+        let chunks = chunker(20, 0).chunk(vec![
+            chunk("Allaitez souvent.", "Allaitement"),
+            chunk("Le bébé boit bien.", "Allaitement"),
+            chunk("Couchez-le sur le dos.", "Sommeil"),
+        ]);
+
+        assert_eq!(
+            chunks,
+            vec![
+                chunk("Allaitez souvent.\nLe bébé boit bien.", "Allaitement"),
+                chunk("Couchez-le sur le dos.", "Sommeil"),
+            ]
+        );
+
+        let sources = chunker(20, 0)
+            .chunk_with_sources(vec![
+                chunk("Allaitez souvent.", "Allaitement"),
+                chunk("Le bébé boit bien.", "Allaitement"),
+                chunk("Couchez-le sur le dos.", "Sommeil"),
+            ])
+            .into_iter()
+            .map(|(_, sources)| sources)
+            .collect::<Vec<_>>();
+        assert_eq!(sources, vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn test_keeps_short_blocks() {
+        let chunker = Chunker::new(ChunkerConfig {
+            target_tokens: 10,
+            max_tokens: 12,
+            overlap_tokens: 0,
+            min_tokens: 4,
+        });
+        let chunks = chunker.chunk(vec![
+            chunk("Le bébé dort beaucoup. Il se réveille.", "Sommeil"),
+            chunk("Et pleure.", "Sommeil"),
+            chunk("Appelez 811.", "Urgence"),
+            chunk(" ", "Urgence"),
+        ]);
+
+        // A short block is merged past the target into its neighbour, or kept alone under
+        // its own heading.
+        assert_eq!(
+            chunks,
+            vec![
+                chunk(
+                    "Le bébé dort beaucoup. Il se réveille.\nEt pleure.",
+                    "Sommeil"
+                ),
+                chunk("Appelez 811.", "Urgence"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_splits_oversized_blocks_at_sentences() {
+        let text = "Le bébé dort beaucoup. Il se réveille pour boire. Il pleure parfois le soir.";
+        let chunks = chunker(10, 0).chunk(vec![chunk(text, "Sommeil")]);
+
+        assert_eq!(
+            chunks,
+            vec![
+                chunk("Le bébé dort beaucoup.", "Sommeil"),
+                chunk("Il se réveille pour boire.", "Sommeil"),
+                chunk("Il pleure parfois le soir.", "Sommeil"),
+            ]
+        );
+        assert!(chunks.iter().all(|c| count_tokens(&c.text) <= 10));
+    }
+
+    #[test]
+    fn test_overlap() {
+        let text = "Le bébé dort beaucoup. Il se réveille pour boire. Il pleure parfois le soir.";
+        let chunks = Chunker::new(ChunkerConfig {
+            max_tokens: 12,
+            ..chunker(10, 7).config
+        })
+        .chunk(vec![chunk(text, "Sommeil"), chunk("Autre.", "Autre")]);
+
+        assert_eq!(
+            chunks,
+            vec![
+                chunk("Le bébé dort beaucoup.", "Sommeil"),
+                chunk(
+                    "Le bébé dort beaucoup. Il se réveille pour boire.",
+                    "Sommeil"
+                ),
+                chunk(
+                    "Il se réveille pour boire. Il pleure parfois le soir.",
+                    "Sommeil"
+                ),
+                chunk("Autre.", "Autre"),
+            ]
+        );
+
+        // Without room for whole sentences, the overlap is cut to stay within the maximum.
+        let chunks = chunker(10, 7).chunk(vec![chunk(text, "Sommeil")]);
+        assert!(chunks.iter().all(|c| count_tokens(&c.text) <= 10));
+        assert_eq!(chunks[1].text, "dort beaucoup. Il se réveille pour boire.");
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use super::Chunk;
+
+/// One way of cleaning up crawled text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanRule {
+    /// Non-breaking and zero-width spaces, soft hyphens and typographic apostrophes become
+    /// their plain equivalents, and the text is normalized to NFC.
+    Unicode,
+    /// Photo and illustration credits, as in "Photo : Mélissa Martin".
+    Credits,
+    /// Navigation and share widget labels, as in "Partager" or "Retour en haut".
+    Widgets,
+    /// Runs of spaces become one space, and blank lines are removed.
+    Whitespace,
+}
+
+impl CleanRule {
+    pub const ALL: [CleanRule; 4] = [
+        CleanRule::Unicode,
+        CleanRule::Credits,
+        CleanRule::Widgets,
+        CleanRule::Whitespace,
+    ];
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            CleanRule::Unicode => normalize_unicode(text),
+            CleanRule::Credits => remove_lines(text, is_credit),
+            CleanRule::Widgets => remove_lines(text, is_widget),
+            CleanRule::Whitespace => collapse_whitespace(text),
+        }
+    }
+}
+
+/// How many chunks each rule altered, or removed by leaving them empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CleanReport {
+    pub rules: BTreeMap<CleanRule, RuleStats>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleStats {
+    pub altered: usize,
+    pub removed: usize,
+}
+
+impl CleanReport {
+    pub fn merge(&mut self, other: &CleanReport) {
+        for (rule, stats) in &other.rules {
+            let total = self.rules.entry(*rule).or_default();
+            total.altered += stats.altered;
+            total.removed += stats.removed;
+        }
+    }
+}
+
+/// Cleans the text of crawled chunks, dropping those left with no text.
+#[derive(Debug, Clone)]
+pub struct TextCleaner {
+    rules: Vec<CleanRule>,
+}
+
+impl Default for TextCleaner {
+    fn default() -> Self {
+        Self::new(CleanRule::ALL.to_vec())
+    }
+}
+
+impl TextCleaner {
+    /// A cleaner applying the given rules, in order.
+    pub fn new(rules: Vec<CleanRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &[CleanRule] {
+        &self.rules
+    }
+
+    pub fn clean<M>(&self, chunks: Vec<Chunk<M>>) -> (Vec<Chunk<M>>, CleanReport) {
+        let mut report = CleanReport::default();
+        for rule in &self.rules {
+            report.rules.insert(*rule, RuleStats::default());
+        }
+
+        let chunks = chunks
+            .into_iter()
+            .filter_map(|mut chunk| {
+                let mut altered_by = vec![];
+
+                for rule in &self.rules {
+                    let text = rule.apply(&chunk.text);
+                    if text == chunk.text {
+                        continue;
+                    }
+
+                    chunk.text = text;
+                    if chunk.text.trim().is_empty() {
+                        report.rules.entry(*rule).or_default().removed += 1;
+                        return None;
+                    }
+                    altered_by.push(*rule);
+                }
+
+                for rule in altered_by {
+                    report.rules.entry(rule).or_default().altered += 1;
+                }
+                Some(chunk)
+            })
+            .collect();
+
+        (chunks, report)
+    }
+
+    /// Applies the rules to text outside of a chunk, such as the text of a link.
+    pub fn clean_text(&self, text: &str) -> String {
+        self.rules
+            .iter()
+            .fold(text.to_string(), |text, rule| rule.apply(&text))
+    }
+}
+
+fn normalize_unicode(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '\u{a0}' | '\u{202f}' | '\u{2007}' => Some(' '),
+            '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{2060}' | '\u{feff}' | '\u{ad}' => None,
+            '\u{2019}' | '\u{2018}' | '\u{02bc}' => Some('\''),
+            c => Some(c),
+        })
+        .nfc()
+        .collect()
+}
+
+fn remove_lines(text: &str, remove: fn(&str) -> bool) -> String {
+    if !text.lines().any(|line| remove(line.trim())) {
+        return text.to_string();
+    }
+
+    text.lines()
+        .filter(|line| !remove(line.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_credit(line: &str) -> bool {
+    const PREFIXES: [&str; 6] = [
+        "photo",
+        "illustration",
+        "crédit",
+        "credit",
+        "source de la photo",
+        "source de l'image",
+    ];
+
+    let line = line.to_lowercase();
+    PREFIXES.iter().any(|prefix| {
+        line.strip_prefix(prefix).is_some_and(|rest| {
+            let rest = rest.strip_prefix('s').unwrap_or(rest);
+            rest.trim_start().starts_with(':')
+        })
+    })
+}
+
+fn is_widget(line: &str) -> bool {
+    const LABELS: [&str; 14] = [
+        "partager",
+        "partager cette page",
+        "partager sur facebook",
+        "partager sur twitter",
+        "partager sur linkedin",
+        "facebook",
+        "twitter",
+        "linkedin",
+        "imprimer",
+        "envoyer par courriel",
+        "retour en haut",
+        "haut de page",
+        "page précédente",
+        "page suivante",
+    ];
+
+    LABELS.contains(&line.to_lowercase().as_str())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CleanRule, RuleStats, TextCleaner};
+    use crate::document::Chunk;
+
+    fn chunk(text: &str) -> Chunk<()> {
+        Chunk::new(text, ())
+    }
+
+    #[test]
+    fn test_clean() {
+        let (chunks, report) = TextCleaner::default().clean(vec![
+            chunk("\n  \n      \n  \n          \n\n\n  \n  \nPhoto : Mélissa Martin"),
+            chunk("L\u{2019}enfant a besoin d\u{2019}attention\u{a0}!"),
+            chunk("Partager\nImprimer"),
+            chunk("Le lait maternel\n\n   suffit  au bébé.\nIllustrations\u{a0}: Jean Untel"),
+            chunk("   \n\t  "),
+            chunk("Cafe\u{301} au lait"),
+        ]);
+
+        let texts = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                "L'enfant a besoin d'attention !",
+                "Le lait maternel\nsuffit au bébé.",
+                "Café au lait",
+            ]
+        );
+
+        assert_eq!(
+            report.rules[&CleanRule::Unicode],
+            RuleStats {
+                altered: 3,
+                removed: 0
+            }
+        );
+        assert_eq!(
+            report.rules[&CleanRule::Credits],
+            RuleStats {
+                altered: 1,
+                removed: 1
+            }
+        );
+        assert_eq!(
+            report.rules[&CleanRule::Widgets],
+            RuleStats {
+                altered: 0,
+                removed: 1
+            }
+        );
+        assert_eq!(
+            report.rules[&CleanRule::Whitespace],
+            RuleStats {
+                altered: 1,
+                removed: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block::BlockType, links::Link, local::LocalFileMetadata, mv::MieuxVivreMetadata, table::Table,
+    web::WebPageMetadata, Chunk, ChunkMetadata,
+};
+
+/// The metadata of a chunk from any source, tagged with the source it came from.
+///
+/// Chunks saved before sources were tagged are all from Mieux Vivre, and still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case", from = "StoredMetadata")]
+pub enum SourceMetadata {
+    MieuxVivre(MieuxVivreMetadata),
+    Web(WebPageMetadata),
+    LocalFile(LocalFileMetadata),
+}
+
+/// [`SourceMetadata`] as saved, with or without its source tag.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredMetadata {
+    Tagged(TaggedMetadata),
+    Legacy(MieuxVivreMetadata),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+enum TaggedMetadata {
+    MieuxVivre(MieuxVivreMetadata),
+    Web(WebPageMetadata),
+    LocalFile(LocalFileMetadata),
+}
+
+impl From<StoredMetadata> for SourceMetadata {
+    fn from(stored: StoredMetadata) -> Self {
+        match stored {
+            StoredMetadata::Tagged(TaggedMetadata::MieuxVivre(m)) | StoredMetadata::Legacy(m) => {
+                SourceMetadata::MieuxVivre(m)
+            }
+            StoredMetadata::Tagged(TaggedMetadata::Web(m)) => SourceMetadata::Web(m),
+            StoredMetadata::Tagged(TaggedMetadata::LocalFile(m)) => SourceMetadata::LocalFile(m),
+        }
+    }
+}
+
+impl From<MieuxVivreMetadata> for SourceMetadata {
+    fn from(metadata: MieuxVivreMetadata) -> Self {
+        SourceMetadata::MieuxVivre(metadata)
+    }
+}
+
+impl From<WebPageMetadata> for SourceMetadata {
+    fn from(metadata: WebPageMetadata) -> Self {
+        SourceMetadata::Web(metadata)
+    }
+}
+
+impl From<LocalFileMetadata> for SourceMetadata {
+    fn from(metadata: LocalFileMetadata) -> Self {
+        SourceMetadata::LocalFile(metadata)
+    }
+}
+
+/// A piece of information identifying where a chunk comes from, for citing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceField {
+    Source,
+    Title,
+    Section,
+    Subsection,
+    Heading,
+    Page,
+    Url,
+    Path,
+}
+
+impl SourceField {
+    /// How to label the field when citing a chunk.
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceField::Source => "Source",
+            SourceField::Title => "Titre",
+            SourceField::Section => "Section",
+            SourceField::Subsection => "Sous-section",
+            SourceField::Heading => "Rubrique",
+            SourceField::Page => "Page",
+            SourceField::Url => "URL",
+            SourceField::Path => "Fichier",
+        }
+    }
+}
+
+/// A source of chunks, re-crawled and replaced as a whole.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Source {
+    MieuxVivre,
+    /// A site, by its [`SiteConfig::name`](super::web::SiteConfig::name).
+    Web(String),
+    LocalFile,
+}
+
+impl Source {
+    /// A key naming the source, as `mieux_vivre`, `web:<site>` or `local_file`.
+    pub fn key(&self) -> String {
+        match self {
+            Source::MieuxVivre => "mieux_vivre".to_string(),
+            Source::Web(site) => format!("web:{}", site),
+            Source::LocalFile => "local_file".to_string(),
+        }
+    }
+}
+
+impl SourceMetadata {
+    pub fn source(&self) -> Source {
+        match self {
+            SourceMetadata::MieuxVivre(_) => Source::MieuxVivre,
+            SourceMetadata::Web(m) => Source::Web(m.site.clone()),
+            SourceMetadata::LocalFile(_) => Source::LocalFile,
+        }
+    }
+
+    /// A short name for where the chunk comes from: "mieux vivre", the site name, or the
+    /// file path.
+    pub fn source_name(&self) -> &str {
+        match self {
+            SourceMetadata::MieuxVivre(_) => "mieux vivre",
+            SourceMetadata::Web(m) => &m.site,
+            SourceMetadata::LocalFile(m) => &m.path,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            SourceMetadata::MieuxVivre(m) => &m.title,
+            SourceMetadata::Web(m) => &m.title,
+            SourceMetadata::LocalFile(m) => &m.title,
+        }
+    }
+
+    /// The URL of the page, or the path of the file, the chunk comes from.
+    pub fn location(&self) -> &str {
+        match self {
+            SourceMetadata::MieuxVivre(m) => &m.url,
+            SourceMetadata::Web(m) => &m.url,
+            SourceMetadata::LocalFile(m) => &m.path,
+        }
+    }
+
+    pub fn heading_path(&self) -> String {
+        match self {
+            SourceMetadata::MieuxVivre(m) => m.heading_path(),
+            SourceMetadata::Web(m) => m.heading_path(),
+            SourceMetadata::LocalFile(m) => m.heading_path(),
+        }
+    }
+
+    /// The table the chunk was extracted from, for chunks holding tabular data.
+    pub fn table(&self) -> Option<&Table> {
+        match self {
+            SourceMetadata::MieuxVivre(m) => m.table.as_ref(),
+            SourceMetadata::Web(m) => m.table.as_ref(),
+            SourceMetadata::LocalFile(_) => None,
+        }
+    }
+
+    /// What the chunk holds. Only Mieux Vivre pages are parsed into figures and callouts.
+    pub fn block_type(&self) -> BlockType {
+        match self {
+            SourceMetadata::MieuxVivre(m) => m.block_type,
+            SourceMetadata::Web(m) if m.table.is_some() => BlockType::Table,
+            SourceMetadata::Web(_) | SourceMetadata::LocalFile(_) => BlockType::Text,
+        }
+    }
+
+    /// The links to other pages in the chunk's content.
+    pub fn links(&self) -> &[Link] {
+        match self {
+            SourceMetadata::MieuxVivre(m) => &m.links,
+            SourceMetadata::Web(_) | SourceMetadata::LocalFile(_) => &[],
+        }
+    }
+
+    /// A chunk's `text` as given to the model: where it comes from, what kind of block it
+    /// is, then its content.
+    pub fn context(&self, text: &str) -> String {
+        format!(
+            "Context from {} ({}){}: {}\n\n",
+            self.source_name(),
+            self.heading_path(),
+            // Warnings and summaries are worth pointing out to the model.
+            self.block_type()
+                .label()
+                .map(|label| format!(" [{}]", label))
+                .unwrap_or_default(),
+            // Tables read better to the model as Markdown than as header/value pairs.
+            self.table()
+                .map_or_else(|| text.to_string(), Table::to_markdown)
+        )
+    }
+
+    /// The [`citation`](Self::citation) as one labelled line per field, followed by a blank
+    /// line.
+    pub fn citation_text(&self) -> String {
+        let lines = self
+            .citation()
+            .into_iter()
+            .map(|(field, value)| format!("{}: {}\n", field.label(), value))
+            .collect::<String>();
+        format!("{}\n", lines)
+    }
+
+    /// What to show to cite the chunk, in order. Only the fields that make sense for the
+    /// chunk's source are included.
+    pub fn citation(&self) -> Vec<(SourceField, String)> {
+        match self {
+            SourceMetadata::MieuxVivre(m) => vec![
+                (SourceField::Source, "Mieux Vivre".to_string()),
+                (SourceField::Title, m.title.clone()),
+                (SourceField::Section, m.section.clone()),
+                (SourceField::Subsection, m.subsection.clone()),
+                (SourceField::Heading, m.heading_path()),
+                (SourceField::Url, m.url.clone()),
+            ],
+            SourceMetadata::Web(m) => vec![
+                (SourceField::Source, m.site.clone()),
+                (SourceField::Title, m.title.clone()),
+                (SourceField::Heading, m.heading_path()),
+                (SourceField::Url, m.url.clone()),
+            ],
+            SourceMetadata::LocalFile(m) => std::iter::once((SourceField::Title, m.title.clone()))
+                .chain(Some((SourceField::Heading, m.heading_path())))
+                .chain(m.page.map(|page| (SourceField::Page, page.to_string())))
+                .chain(Some((SourceField::Path, m.path.clone())))
+                .collect(),
+        }
+    }
+}
+
+impl ChunkMetadata for SourceMetadata {
+    fn url(&self) -> &str {
+        self.location()
+    }
+
+    fn heading_path(&self) -> String {
+        self.heading_path()
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some(self.title())
+    }
+}
+
+/// Chunks from any number of sources, saved together.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Corpus {
+    chunks: Vec<Chunk<SourceMetadata>>,
+}
+
+impl Corpus {
+    pub fn new(chunks: Vec<Chunk<SourceMetadata>>) -> Self {
+        Self { chunks }
+    }
+
+    /// Loads a corpus, or an empty corpus if the file does not exist yet. Chunks saved
+    /// before chunk IDs existed are given one.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut chunks: Vec<Chunk<SourceMetadata>> = match std::fs::read(path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        if chunks.iter().any(|chunk| chunk.id.is_empty()) {
+            Chunk::assign_ids(&mut chunks);
+        }
+
+        Ok(Self { chunks })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.chunks)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn chunks(&self) -> &[Chunk<SourceMetadata>] {
+        &self.chunks
+    }
+
+    pub fn into_chunks(self) -> Vec<Chunk<SourceMetadata>> {
+        self.chunks
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The chunks of one source, with their own metadata type.
+    pub fn select<M>(&self, select: impl Fn(&SourceMetadata) -> Option<&M>) -> Vec<Chunk<M>>
+    where
+        M: Clone,
+    {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| {
+                Some(Chunk {
+                    id: chunk.id.clone(),
+                    text: chunk.text.clone(),
+                    metadata: select(&chunk.metadata)?.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces every chunk for which `replaced` is true with `chunks`, where the first
+    /// replaced chunk was, or at the end if there was none. Sources keep their place in the
+    /// corpus across re-crawls.
+    pub fn replace<M: Into<SourceMetadata>>(
+        &mut self,
+        replaced: impl Fn(&SourceMetadata) -> bool,
+        chunks: Vec<Chunk<M>>,
+    ) {
+        let position = self
+            .chunks
+            .iter()
+            .position(|chunk| replaced(&chunk.metadata))
+            .unwrap_or(self.chunks.len());
+        self.chunks.retain(|chunk| !replaced(&chunk.metadata));
+
+        let chunks = chunks.into_iter().map(|chunk| Chunk {
+            id: chunk.id,
+            text: chunk.text,
+            metadata: chunk.metadata.into(),
+        });
+        self.chunks.splice(position..position, chunks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Corpus, SourceField, SourceMetadata};
+    use crate::document::{
+        local::{FileKind, LocalFileMetadata},
+        mv::MieuxVivreMetadata,
+        Chunk,
+    };
+
+    const LEGACY_JSON: &str = r#"[{
+        "text": "Le premier trimestre couvre les 13 premières semaines.",
+        "metadata": {
+            "title": "Les étapes de la grossesse",
+            "section": "Grossesse",
+            "subsection": "Étapes",
+            "heading": "Le premier trimestre",
+            "url": "https://www.inspq.qc.ca/mieux-vivre/grossesse/les-etapes"
+        }
+    }]"#;
+
+    fn local_chunk(text: &str) -> Chunk<LocalFileMetadata> {
+        let mut chunks = vec![Chunk::new(
+            text,
+            LocalFileMetadata {
+                path: "conge.pdf".to_string(),
+                kind: FileKind::Pdf,
+                title: "conge".to_string(),
+                page: Some(2),
+                heading: None,
+                headings: vec!["conge".to_string()],
+                heading_level: None,
+            },
+        )];
+        Chunk::assign_ids(&mut chunks);
+        chunks.remove(0)
+    }
+
+    #[test]
+    fn test_load_legacy_and_tagged_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chunks.json");
+        std::fs::write(&path, LEGACY_JSON).unwrap();
+
+        let mut corpus = Corpus::load(&path).unwrap();
+        assert_eq!(corpus.len(), 1);
+        assert!(!corpus.chunks()[0].id.is_empty());
+        let SourceMetadata::MieuxVivre(metadata) = &corpus.chunks()[0].metadata else {
+            panic!("legacy chunks are from Mieux Vivre");
+        };
+        assert_eq!(
+            metadata.heading_path(),
+            "Les étapes de la grossesse > Le premier trimestre"
+        );
+
+        corpus.replace(
+            |m| matches!(m, SourceMetadata::LocalFile(_)),
+            vec![local_chunk("Consultez si le bébé boit mal.")],
+        );
+        corpus.save(&path).unwrap();
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(json.contains(r#""source": "mieux_vivre""#));
+        assert!(json.contains(r#""source": "local_file""#));
+        assert_eq!(Corpus::load(&path).unwrap(), corpus);
+
+        let mieux_vivre: Vec<Chunk<MieuxVivreMetadata>> = corpus.select(|m| match m {
+            SourceMetadata::MieuxVivre(m) => Some(m),
+            _ => None,
+        });
+        assert_eq!(mieux_vivre.len(), 1);
+    }
+
+    #[test]
+    fn test_replace_keeps_source_positions() {
+        let mut corpus = Corpus::default();
+        corpus.replace(|_| false, vec![local_chunk("Premier.")]);
+        let legacy: Vec<Chunk<SourceMetadata>> = serde_json::from_str(LEGACY_JSON).unwrap();
+        corpus.replace(|m| matches!(m, SourceMetadata::MieuxVivre(_)), legacy);
+        corpus.replace(
+            |m| matches!(m, SourceMetadata::LocalFile(_)),
+            vec![local_chunk("Deuxième."), local_chunk("Troisième.")],
+        );
+
+        let texts = corpus
+            .chunks()
+            .iter()
+            .map(|c| c.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                "Deuxième.",
+                "Troisième.",
+                "Le premier trimestre couvre les 13 premières semaines."
+            ]
+        );
+
+        let citation = corpus.chunks()[0].metadata.citation();
+        assert_eq!(
+            citation.iter().map(|(field, _)| *field).collect::<Vec<_>>(),
+            vec![
+                SourceField::Title,
+                SourceField::Heading,
+                SourceField::Page,
+                SourceField::Path
+            ]
+        );
+        assert_eq!(
+            corpus.chunks()[0].metadata.citation_text(),
+            "Titre: conge\nRubrique: conge\nPage: 2\nFichier: conge.pdf\n\n"
+        );
+        assert_eq!(
+            corpus.chunks()[0].metadata.context("Deuxième."),
+            "Context from conge.pdf (conge): Deuxième.\n\n"
+        );
+    }
+}
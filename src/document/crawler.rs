@@ -0,0 +1,333 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::Url;
+use tokio::sync::Mutex;
+
+use super::{error::CrawlError, robots::RobotsTxt, snapshot::Snapshot, state::PageState};
+use crate::http::{send_with_retry, RateLimiter, RequestRate, RetryPolicy};
+
+/// How politely to crawl a site.
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    pub user_agent: String,
+    pub retry: RetryPolicy,
+    /// The maximum number of requests sent per second, if any.
+    pub requests_per_second: Option<RequestRate>,
+    /// The maximum number of pages fetched at the same time.
+    pub max_concurrency: usize,
+    pub timeout: Duration,
+    pub respect_robots_txt: bool,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!(
+                "bebe-ai/",
+                env!("CARGO_PKG_VERSION"),
+                " (+https://github.com/xuorig/bebe-ai)"
+            )
+            .to_string(),
+            retry: RetryPolicy::default(),
+            requests_per_second: Some(RequestRate::new(5.0).unwrap()),
+            max_concurrency: 25,
+            timeout: Duration::from_secs(30),
+            respect_robots_txt: true,
+        }
+    }
+}
+
+/// Where a crawler reads pages from.
+#[derive(Debug, Clone)]
+pub enum PageSource {
+    /// Fetch every page from the live site.
+    Live,
+    /// Fetch every page from the live site and save it to a snapshot as we go.
+    Record(Snapshot),
+    /// Read every page from a previously recorded snapshot, without hitting the network.
+    Replay(Snapshot),
+}
+
+/// A page as returned by a conditional request.
+#[derive(Debug)]
+pub enum Fetched {
+    Page {
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    NotModified,
+}
+
+/// Fetches pages from a [`PageSource`], following a [`CrawlConfig`].
+#[derive(Debug)]
+pub struct Crawler {
+    source: PageSource,
+    config: CrawlConfig,
+    client: reqwest::Client,
+    limiter: Option<RateLimiter>,
+    /// robots.txt rules by origin, fetched on first use.
+    robots: Mutex<HashMap<String, RobotsTxt>>,
+}
+
+impl Crawler {
+    pub fn new(source: PageSource, config: CrawlConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(config.user_agent.clone())
+            .timeout(config.timeout)
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            source,
+            limiter: config.requests_per_second.map(RateLimiter::new),
+            config,
+            client,
+            robots: Mutex::default(),
+        }
+    }
+
+    pub fn config(&self) -> &CrawlConfig {
+        &self.config
+    }
+
+    pub async fn get(&self, url: &str) -> Result<String, CrawlError> {
+        match self.get_if_modified(url, None).await? {
+            Fetched::Page { body, .. } => Ok(body),
+            Fetched::NotModified => Err(CrawlError::UnexpectedNotModified {
+                url: url.to_string(),
+            }),
+        }
+    }
+
+    /// Fetches a page, asking the server not to send it again if it is unchanged since
+    /// `previous`. Recording and replaying a snapshot always fetch the full page.
+    pub async fn get_if_modified(
+        &self,
+        url: &str,
+        previous: Option<&PageState>,
+    ) -> Result<Fetched, CrawlError> {
+        match &self.source {
+            PageSource::Live => self.get_live(url, previous).await,
+            PageSource::Record(snapshot) => {
+                let fetched = self.get_live(url, None).await?;
+                let Fetched::Page { body, .. } = &fetched else {
+                    return Err(CrawlError::UnexpectedNotModified {
+                        url: url.to_string(),
+                    });
+                };
+                snapshot
+                    .write(url, body)
+                    .await
+                    .map_err(|source| CrawlError::Snapshot {
+                        url: url.to_string(),
+                        source,
+                    })?;
+                Ok(fetched)
+            }
+            PageSource::Replay(snapshot) => snapshot
+                .read(url)
+                .await
+                .map(|body| Fetched::Page {
+                    body,
+                    etag: None,
+                    last_modified: None,
+                })
+                .map_err(|source| CrawlError::Snapshot {
+                    url: url.to_string(),
+                    source,
+                }),
+        }
+    }
+
+    async fn get_live(
+        &self,
+        url: &str,
+        previous: Option<&PageState>,
+    ) -> Result<Fetched, CrawlError> {
+        if !self.is_allowed(url).await {
+            return Err(CrawlError::Disallowed {
+                url: url.to_string(),
+            });
+        }
+
+        let http_error = |source| CrawlError::Http {
+            url: url.to_string(),
+            source,
+        };
+
+        let mut request = self.client.get(url);
+        if let Some(previous) = previous {
+            if let Some(etag) = &previous.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &previous.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = send_with_retry(request, &self.config.retry, self.limiter.as_ref())
+            .await
+            .map_err(http_error)?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+
+        let response = response.error_for_status().map_err(http_error)?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(String::from)
+        };
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+
+        Ok(Fetched::Page {
+            body: response.text().await.map_err(http_error)?,
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Checks a URL against its site's robots.txt. A robots.txt that cannot be fetched
+    /// allows everything.
+    async fn is_allowed(&self, url: &str) -> bool {
+        if !self.config.respect_robots_txt {
+            return true;
+        }
+
+        let Ok(url) = Url::parse(url) else {
+            return true;
+        };
+        let origin = url.origin().ascii_serialization();
+
+        // Holding the lock while fetching makes concurrent pages wait for the first fetch.
+        let mut robots = self.robots.lock().await;
+        if !robots.contains_key(&origin) {
+            let rules = self.fetch_robots_txt(&origin).await;
+            robots.insert(origin.clone(), rules);
+        }
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        robots[&origin].is_allowed(&path)
+    }
+
+    async fn fetch_robots_txt(&self, origin: &str) -> RobotsTxt {
+        let url = format!("{}/robots.txt", origin);
+        let request = self.client.get(&url);
+
+        let response = send_with_retry(request, &self.config.retry, self.limiter.as_ref()).await;
+        match response.and_then(|response| response.error_for_status()) {
+            Ok(response) => match response.text().await {
+                Ok(body) => RobotsTxt::parse(&body, &self.config.user_agent),
+                Err(_) => RobotsTxt::default(),
+            },
+            Err(e) => {
+                tracing::debug!("No robots.txt at {}: {}", url, e);
+                RobotsTxt::default()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{http::HeaderMap, response::IntoResponse, routing::get, Router};
+
+    use super::{CrawlConfig, Crawler, Fetched, PageSource};
+    use crate::{
+        document::{error::CrawlError, state::PageState},
+        http::RetryPolicy,
+        test_util,
+    };
+
+    async fn serve() -> String {
+        async fn page(headers: HeaderMap) -> axum::response::Response {
+            if headers.get("if-none-match").is_some_and(|v| v == "\"v1\"") {
+                return axum::http::StatusCode::NOT_MODIFIED.into_response();
+            }
+            ([("etag", "\"v1\"")], "<h1>Page</h1>").into_response()
+        }
+
+        let app = Router::new()
+            .route("/page", get(page))
+            .route(
+                "/stale",
+                get(|| async { axum::http::StatusCode::NOT_MODIFIED }),
+            )
+            .route("/private/page", get(page))
+            .route(
+                "/robots.txt",
+                get(|| async { "User-agent: *\nDisallow: /private\n" }),
+            );
+
+        test_util::serve(app).await
+    }
+
+    fn config() -> CrawlConfig {
+        CrawlConfig {
+            retry: RetryPolicy::none(),
+            requests_per_second: None,
+            timeout: Duration::from_secs(5),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_request() {
+        let base = serve().await;
+        let url = format!("{}/page", base);
+        let crawler = Crawler::new(PageSource::Live, config());
+
+        let fetched = crawler.get_if_modified(&url, None).await.unwrap();
+        let Fetched::Page { etag, .. } = fetched else {
+            panic!("expected a full page");
+        };
+        assert_eq!(etag.as_deref(), Some("\"v1\""));
+
+        let previous = PageState {
+            etag,
+            ..Default::default()
+        };
+        let fetched = crawler
+            .get_if_modified(&url, Some(&previous))
+            .await
+            .unwrap();
+        assert!(matches!(fetched, Fetched::NotModified));
+
+        // A server answering 304 without being asked fails the page, not the crawl.
+        let url = format!("{}/stale", base);
+        assert!(matches!(
+            crawler.get(&url).await,
+            Err(CrawlError::UnexpectedNotModified { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_robots_txt() {
+        let base = serve().await;
+        let url = format!("{}/private/page", base);
+
+        let crawler = Crawler::new(PageSource::Live, config());
+        assert!(matches!(
+            crawler.get(&url).await,
+            Err(CrawlError::Disallowed { .. })
+        ));
+
+        let crawler = Crawler::new(
+            PageSource::Live,
+            CrawlConfig {
+                respect_robots_txt: false,
+                ..config()
+            },
+        );
+        assert!(crawler.get(&url).await.is_ok());
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+use similar::TextDiff;
+
+use super::{
+    corpus::{Corpus, SourceMetadata},
+    Chunk,
+};
+
+/// What changed between two crawls, compared page by page and heading by heading.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CorpusDiff {
+    /// Pages, by URL or file path, only in the new corpus.
+    pub added_pages: Vec<String>,
+    /// Pages only in the old corpus.
+    pub removed_pages: Vec<String>,
+    /// Headings only in the new corpus, on pages in both.
+    pub added_sections: Vec<Section>,
+    /// Headings only in the old corpus, on pages in both.
+    pub removed_sections: Vec<Section>,
+    /// Headings in both whose text changed.
+    pub changed_text: Vec<TextChange>,
+    /// Headings in both whose metadata changed.
+    pub changed_metadata: Vec<MetadataChange>,
+}
+
+/// A heading on a page.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Section {
+    pub url: String,
+    /// The headings below the page title, as in "Positions > Position madone", or empty
+    /// for the content directly under the title. Leaving the title out lets a renamed page
+    /// show as a metadata change rather than as every section removed and added.
+    pub heading: String,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.heading.is_empty() {
+            write!(f, "top of {}", self.url)
+        } else {
+            write!(f, "{} ({})", self.heading, self.url)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TextChange {
+    #[serde(flatten)]
+    pub section: Section,
+    /// A unified diff of the text of every chunk under the heading.
+    pub diff: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetadataChange {
+    #[serde(flatten)]
+    pub section: Section,
+    pub fields: Vec<FieldChange>,
+}
+
+/// A metadata field that changed, with its old and new values as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// The chunks under one heading, merged.
+#[derive(Default)]
+struct SectionContent {
+    text: String,
+    /// Every value each metadata field takes across the chunks, sorted.
+    metadata: BTreeMap<String, Vec<serde_json::Value>>,
+}
+
+fn sections(corpus: &Corpus) -> BTreeMap<Section, SectionContent> {
+    let mut sections: BTreeMap<Section, SectionContent> = BTreeMap::new();
+    for Chunk { text, metadata, .. } in corpus.chunks() {
+        let heading_path = metadata.heading_path();
+        let heading = match heading_path.strip_prefix(metadata.title()) {
+            Some("") => "",
+            Some(below) => below.strip_prefix(" > ").unwrap_or(&heading_path),
+            None => &heading_path,
+        };
+        let section = Section {
+            url: metadata.location().to_string(),
+            heading: heading.to_string(),
+        };
+
+        let content = sections.entry(section).or_default();
+        if !content.text.is_empty() {
+            content.text.push('\n');
+        }
+        content.text.push_str(text);
+        if let serde_json::Value::Object(fields) = metadata_fields(metadata) {
+            for (field, value) in fields {
+                let values = content.metadata.entry(field).or_default();
+                if !values.contains(&value) {
+                    values.push(value);
+                    values.sort_by_key(|value| value.to_string());
+                }
+            }
+        }
+    }
+    sections
+}
+
+/// The metadata of a chunk as JSON, leaving out the fields that are part of the section key
+/// or differ between chunks of the same section.
+fn metadata_fields(metadata: &SourceMetadata) -> serde_json::Value {
+    let mut value = serde_json::to_value(metadata).expect("metadata serializes to JSON");
+    if let Some(fields) = value.as_object_mut() {
+        for key in [
+            "url",
+            "path",
+            "headings",
+            "heading",
+            "heading_level",
+            "table",
+            "block_type",
+            "figure",
+            "links",
+        ] {
+            fields.remove(key);
+        }
+    }
+    value
+}
+
+/// The fields whose values differ between two sections. A field taking several values
+/// across a section's chunks is shown as an array of them.
+fn field_changes(
+    old: &BTreeMap<String, Vec<serde_json::Value>>,
+    new: &BTreeMap<String, Vec<serde_json::Value>>,
+) -> Vec<FieldChange> {
+    let value = |values: Option<&Vec<serde_json::Value>>| match values.map(Vec::as_slice) {
+        None | Some([]) => serde_json::Value::Null,
+        Some([value]) => value.clone(),
+        Some(values) => serde_json::Value::Array(values.to_vec()),
+    };
+
+    let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = value(old.get(key));
+            let new = value(new.get(key));
+            (old != new).then(|| FieldChange {
+                field: key.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// A line diff, with a trailing newline added to both texts so the last line does not get
+/// a "No newline at end of file" marker.
+fn text_diff(old: &str, new: &str) -> String {
+    let (old, new) = (format!("{}\n", old), format!("{}\n", new));
+    TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(2)
+        .to_string()
+}
+
+impl CorpusDiff {
+    pub fn new(old: &Corpus, new: &Corpus) -> Self {
+        let old_sections = sections(old);
+        let new_sections = sections(new);
+
+        let pages = |sections: &BTreeMap<Section, SectionContent>| {
+            sections
+                .keys()
+                .map(|section| section.url.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        let old_pages = pages(&old_sections);
+        let new_pages = pages(&new_sections);
+
+        let mut diff = CorpusDiff {
+            added_pages: new_pages.difference(&old_pages).cloned().collect(),
+            removed_pages: old_pages.difference(&new_pages).cloned().collect(),
+            ..Default::default()
+        };
+
+        for (section, new_content) in &new_sections {
+            let Some(old_content) = old_sections.get(section) else {
+                if old_pages.contains(&section.url) {
+                    diff.added_sections.push(section.clone());
+                }
+                continue;
+            };
+
+            if old_content.text != new_content.text {
+                diff.changed_text.push(TextChange {
+                    section: section.clone(),
+                    diff: text_diff(&old_content.text, &new_content.text),
+                });
+            }
+
+            let fields = field_changes(&old_content.metadata, &new_content.metadata);
+            if !fields.is_empty() {
+                diff.changed_metadata.push(MetadataChange {
+                    section: section.clone(),
+                    fields,
+                });
+            }
+        }
+
+        diff.removed_sections = old_sections
+            .keys()
+            .filter(|section| new_pages.contains(&section.url))
+            .filter(|section| !new_sections.contains_key(section))
+            .cloned()
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CorpusDiff::default()
+    }
+}
+
+impl fmt::Display for CorpusDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+
+        writeln!(
+            f,
+            "{} pages added, {} removed; {} headings added, {} removed; {} with changed text, {} with changed metadata",
+            self.added_pages.len(),
+            self.removed_pages.len(),
+            self.added_sections.len(),
+            self.removed_sections.len(),
+            self.changed_text.len(),
+            self.changed_metadata.len()
+        )?;
+
+        for url in &self.added_pages {
+            writeln!(f, "\n+ page {}", url)?;
+        }
+        for url in &self.removed_pages {
+            writeln!(f, "\n- page {}", url)?;
+        }
+        for section in &self.added_sections {
+            writeln!(f, "\n+ heading {}", section)?;
+        }
+        for section in &self.removed_sections {
+            writeln!(f, "\n- heading {}", section)?;
+        }
+        for change in &self.changed_text {
+            writeln!(f, "\n~ text of {}", change.section)?;
+            write!(f, "{}", change.diff)?;
+        }
+        for change in &self.changed_metadata {
+            writeln!(f, "\n~ metadata of {}", change.section)?;
+            for field in &change.fields {
+                writeln!(f, "  {}: {} -> {}", field.field, field.old, field.new)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CorpusDiff, Section};
+    use crate::document::{corpus::Corpus, mv::MieuxVivreMetadata, Chunk};
+
+    fn chunk(
+        page: &str,
+        heading: Option<&str>,
+        section: &str,
+        text: &str,
+    ) -> Chunk<MieuxVivreMetadata> {
+        let title = "Titre".to_string();
+        Chunk::new(
+            text,
+            MieuxVivreMetadata {
+                title: title.clone(),
+                section: section.to_string(),
+                subsection: String::new(),
+                heading: heading.map(String::from),
+                headings: std::iter::once(title)
+                    .chain(heading.map(String::from))
+                    .collect(),
+                heading_level: heading.map(|_| 2),
+                url: format!("https://example.com/{}", page),
+                table: None,
+                block_type: Default::default(),
+                figure: None,
+                links: vec![],
+            },
+        )
+    }
+
+    fn corpus(chunks: Vec<Chunk<MieuxVivreMetadata>>) -> Corpus {
+        let mut corpus = Corpus::default();
+        corpus.replace(|_| true, chunks);
+        corpus
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = corpus(vec![
+            chunk("a", None, "Grossesse", "Intro."),
+            chunk(
+                "a",
+                Some("Nausées"),
+                "Grossesse",
+                "Mangez peu.\nBuvez souvent.",
+            ),
+            chunk("a", Some("Fatigue"), "Grossesse", "Reposez-vous."),
+            chunk("b", None, "Grossesse", "Vieille page."),
+        ]);
+        let new = corpus(vec![
+            chunk("a", None, "Grossesse et accouchement", "Intro."),
+            chunk(
+                "a",
+                Some("Nausées"),
+                "Grossesse et accouchement",
+                "Mangez peu.\nBuvez de l'eau.",
+            ),
+            chunk("a", Some("Sommeil"), "Grossesse et accouchement", "Dormez."),
+            chunk("c", None, "Bébé", "Nouvelle page."),
+        ]);
+
+        let diff = CorpusDiff::new(&old, &new);
+        assert_eq!(diff.added_pages, vec!["https://example.com/c"]);
+        assert_eq!(diff.removed_pages, vec!["https://example.com/b"]);
+        let section = |heading: &str| Section {
+            url: "https://example.com/a".to_string(),
+            heading: heading.to_string(),
+        };
+        assert_eq!(diff.added_sections, vec![section("Sommeil")]);
+        assert_eq!(diff.removed_sections, vec![section("Fatigue")]);
+
+        assert_eq!(diff.changed_text.len(), 1);
+        assert_eq!(diff.changed_text[0].section, section("Nausées"));
+        assert!(diff.changed_text[0]
+            .diff
+            .contains("-Buvez souvent.\n+Buvez de l'eau.\n"));
+
+        assert_eq!(diff.changed_metadata.len(), 2);
+        assert_eq!(diff.changed_metadata[0].fields[0].field, "section");
+
+        let text = diff.to_string();
+        assert!(text.contains("+ page https://example.com/c"));
+        assert!(text.contains("~ text of Nausées (https://example.com/a)"));
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["changed_text"][0]["heading"], "Nausées");
+
+        assert!(CorpusDiff::new(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_renamed_page() {
+        let renamed = |mut chunk: Chunk<MieuxVivreMetadata>| {
+            chunk.metadata.title = "Nouveau titre".to_string();
+            chunk.metadata.headings[0] = chunk.metadata.title.clone();
+            chunk
+        };
+        let old = corpus(vec![
+            chunk("a", None, "Grossesse", "Intro."),
+            chunk("a", Some("Nausées"), "Grossesse", "Mangez peu."),
+        ]);
+        let mut nausea = chunk("a", Some("Nausées"), "Grossesse", "Mangez peu.");
+        nausea.metadata.subsection = "Malaises".to_string();
+        let new = corpus(vec![
+            renamed(chunk("a", None, "Grossesse", "Intro.")),
+            renamed(chunk("a", Some("Nausées"), "Grossesse", "Buvez.")),
+            renamed(nausea),
+        ]);
+
+        let diff = CorpusDiff::new(&old, &new);
+        assert!(diff.added_sections.is_empty() && diff.removed_sections.is_empty());
+        assert_eq!(diff.changed_text.len(), 1);
+
+        let changes = diff
+            .changed_metadata
+            .iter()
+            .map(|change| {
+                let fields = change
+                    .fields
+                    .iter()
+                    .map(|f| f.field.as_str())
+                    .collect::<Vec<_>>();
+                (change.section.heading.as_str(), fields)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("", vec!["title"]),
+                ("Nausées", vec!["subsection", "title"])
+            ]
+        );
+        // Every value a field takes across the section's chunks is compared.
+        assert_eq!(
+            diff.changed_metadata[1].fields[0].new,
+            serde_json::json!(["", "Malaises"])
+        );
+    }
+}
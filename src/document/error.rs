@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// Why a page could not be crawled. Every variant carries the URL of the failing page.
+#[derive(Debug)]
+pub enum CrawlError {
+    /// The request failed, or the server answered with an error status.
+    Http { url: String, source: reqwest::Error },
+    /// The page could not be read from or written to a snapshot.
+    Snapshot { url: String, source: std::io::Error },
+    /// The page has no title element.
+    MissingTitle { url: String },
+    /// The page has no main content container.
+    MissingContent { url: String },
+    /// A navigation menu or section link could not be parsed.
+    MalformedMenu { url: String, reason: String },
+    /// The site's robots.txt does not allow crawling the page.
+    Disallowed { url: String },
+    /// The server answered 304 Not Modified to a request that was not conditional.
+    UnexpectedNotModified { url: String },
+}
+
+impl CrawlError {
+    pub fn url(&self) -> &str {
+        match self {
+            CrawlError::Http { url, .. }
+            | CrawlError::Snapshot { url, .. }
+            | CrawlError::MissingTitle { url }
+            | CrawlError::MissingContent { url }
+            | CrawlError::MalformedMenu { url, .. }
+            | CrawlError::Disallowed { url }
+            | CrawlError::UnexpectedNotModified { url } => url,
+        }
+    }
+}
+
+impl fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrawlError::Http { url, source } => write!(f, "failed to fetch {}: {}", url, source),
+            CrawlError::Snapshot { url, source } => {
+                write!(f, "failed to access snapshot of {}: {}", url, source)
+            }
+            CrawlError::MissingTitle { url } => write!(f, "no title found on {}", url),
+            CrawlError::MissingContent { url } => write!(f, "no content found on {}", url),
+            CrawlError::MalformedMenu { url, reason } => {
+                write!(f, "malformed menu on {}: {}", url, reason)
+            }
+            CrawlError::Disallowed { url } => write!(f, "robots.txt disallows {}", url),
+            CrawlError::UnexpectedNotModified { url } => {
+                write!(f, "{} answered 304 to an unconditional request", url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CrawlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CrawlError::Http { source, .. } => Some(source),
+            CrawlError::Snapshot { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
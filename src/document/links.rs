@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use reqwest::Url;
+use scraper::{ElementRef, Selector};
+use serde::{Deserialize, Serialize};
+
+use super::{clean::TextCleaner, Chunk, ChunkMetadata};
+
+/// A link from the content of a chunk to another page.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Link {
+    /// The absolute URL of the linked page, without its fragment.
+    pub url: String,
+    /// The text of the link, as in "voir la section sur l'allaitement".
+    pub text: String,
+}
+
+impl Link {
+    /// Finds the links within an element, resolved against the URL of the page they are on.
+    /// Links to the page itself and links other than http(s), such as mailto:, are left out.
+    pub fn parse(element: ElementRef, page_url: &str) -> Vec<Link> {
+        let selector = Selector::parse("a[href]").unwrap();
+        let Ok(base) = Url::parse(page_url) else {
+            return vec![];
+        };
+        let page = without_fragment(base.clone());
+
+        element
+            .select(&selector)
+            .filter_map(|a| {
+                let url = base.join(a.value().attr("href")?).ok()?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    return None;
+                }
+                let url = without_fragment(url);
+                let text = a.text().collect::<String>();
+                (url != page).then(|| Link {
+                    url,
+                    text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                })
+            })
+            .collect()
+    }
+}
+
+fn without_fragment(mut url: Url) -> String {
+    url.set_fragment(None);
+    url.into()
+}
+
+/// Gives each chunk the links found in the blocks it was made from, with `chunks` as returned
+/// by [`Chunker::chunk_with_sources`] and `block_links` holding the links of each block. Links
+/// are kept apart from the blocks' metadata while chunking, as blocks are merged into chunks
+/// only when their metadata is equal.
+///
+/// [`Chunker::chunk_with_sources`]: super::chunker::Chunker::chunk_with_sources
+pub fn attach_links<M>(
+    chunks: Vec<(Chunk<M>, Vec<usize>)>,
+    block_links: &[Vec<Link>],
+    cleaner: &TextCleaner,
+    chunk_links: impl Fn(&mut M) -> &mut Vec<Link>,
+) -> Vec<Chunk<M>> {
+    chunks
+        .into_iter()
+        .map(|(mut chunk, sources)| {
+            let mut found: Vec<Link> = vec![];
+            for link in sources.iter().flat_map(|&i| block_links.get(i)).flatten() {
+                let link = Link {
+                    text: cleaner
+                        .clean_text(&link.text)
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    ..link.clone()
+                };
+                if !found.contains(&link) {
+                    found.push(link);
+                }
+            }
+            *chunk_links(&mut chunk.metadata) = found;
+            chunk
+        })
+        .collect()
+}
+
+/// Which pages link to which, from the links in the content of their chunks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkGraph {
+    /// Every page of the corpus, with the pages its content links to.
+    pub pages: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl LinkGraph {
+    /// The graph of the `crawled` pages, such as those of [`CrawlState::pages`], with the
+    /// links of their chunks. Pages without chunks are in the graph, without links.
+    ///
+    /// [`CrawlState::pages`]: super::state::CrawlState::pages
+    pub fn new<M: ChunkMetadata>(
+        crawled: impl IntoIterator<Item = String>,
+        chunks: &[Chunk<M>],
+        links: impl Fn(&M) -> &[Link],
+    ) -> Self {
+        let mut pages: BTreeMap<String, BTreeSet<String>> = crawled
+            .into_iter()
+            .map(|page| (page, BTreeSet::new()))
+            .collect();
+        for chunk in chunks {
+            pages
+                .entry(chunk.metadata.url().to_string())
+                .or_default()
+                .extend(links(&chunk.metadata).iter().map(|link| link.url.clone()));
+        }
+        Self { pages }
+    }
+
+    /// Loads a graph saved by [`LinkGraph::save`], or an empty graph if there is none.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    /// The pages linking to `url`.
+    pub fn links_to(&self, url: &str) -> Vec<&str> {
+        self.pages
+            .iter()
+            .filter(|(_, targets)| targets.contains(url))
+            .map(|(page, _)| page.as_str())
+            .collect()
+    }
+
+    /// The pages of the corpus linked from or to `url`, to use as supporting context when
+    /// `url` is retrieved.
+    pub fn neighbors(&self, url: &str) -> BTreeSet<&str> {
+        self.pages
+            .get(url)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|target| self.pages.contains_key(*target))
+            .chain(self.links_to(url))
+            .collect()
+    }
+
+    /// The pages no other page links to, reachable only through menus.
+    pub fn orphans(&self) -> Vec<&str> {
+        let linked = self
+            .pages
+            .iter()
+            .flat_map(|(page, targets)| targets.iter().filter(move |target| *target != page))
+            .collect::<BTreeSet<_>>();
+        self.pages
+            .keys()
+            .filter(|page| !linked.contains(page))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The links, as `(page, target)`, to pages under `scope` that are not in the corpus.
+    /// These point to pages that moved or were removed, or that the crawl did not reach.
+    pub fn broken(&self, scope: &str) -> Vec<(&str, &str)> {
+        self.pages
+            .iter()
+            .flat_map(|(page, targets)| targets.iter().map(move |target| (page, target)))
+            .filter(|(_, target)| target.starts_with(scope) && !self.pages.contains_key(*target))
+            .map(|(page, target)| (page.as_str(), target.as_str()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::{attach_links, Link, LinkGraph};
+    use crate::document::{clean::TextCleaner, Chunk, ChunkMetadata};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Page(&'static str, Vec<Link>);
+
+    impl ChunkMetadata for Page {
+        fn url(&self) -> &str {
+            self.0
+        }
+
+        fn heading_path(&self) -> String {
+            "Titre".to_string()
+        }
+    }
+
+    fn link(url: &str, text: &str) -> Link {
+        Link {
+            url: url.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_attach_links() {
+        let html = Html::parse_fragment(
+            r##"<p>Voir <a href="../allaitement#positions">la section sur
+            l’allaitement</a>, <a href="#haut">le haut</a>, <a href="mailto:a@b.ca">écrire</a>
+            ou <a href="https://ailleurs.com/">un autre site</a>.</p>"##,
+        );
+        let url = "https://example.com/guide/bebe/sommeil";
+        let links = Link::parse(html.root_element(), url);
+        assert_eq!(
+            links,
+            vec![
+                link(
+                    "https://example.com/guide/allaitement",
+                    "la section sur l’allaitement"
+                ),
+                link("https://ailleurs.com/", "un autre site"),
+            ]
+        );
+
+        // The first chunk is made of the block holding the links and the one after it; the
+        // same link text elsewhere on the page does not matter.
+        let chunks = vec![
+            (
+                Chunk::new(
+                    "Voir la section sur l'allaitement.\nSuite.",
+                    Page(url, vec![]),
+                ),
+                vec![0, 1],
+            ),
+            (
+                Chunk::new("Voir la section sur l'allaitement.", Page(url, vec![])),
+                vec![2],
+            ),
+        ];
+        let block_links = vec![links, vec![], vec![]];
+        let chunks = attach_links(chunks, &block_links, &TextCleaner::default(), |m| &mut m.1);
+        assert_eq!(
+            chunks[0].metadata.1,
+            vec![
+                link(
+                    "https://example.com/guide/allaitement",
+                    "la section sur l'allaitement"
+                ),
+                link("https://ailleurs.com/", "un autre site"),
+            ]
+        );
+        assert!(chunks[1].metadata.1.is_empty());
+    }
+
+    #[test]
+    fn test_link_graph() {
+        let chunks = vec![
+            Chunk::new("", Page("https://a.ca/1", vec![link("https://a.ca/2", "")])),
+            Chunk::new("", Page("https://a.ca/1", vec![link("https://a.ca/3", "")])),
+            Chunk::new("", Page("https://a.ca/2", vec![link("https://b.ca/", "")])),
+            Chunk::new("", Page("https://a.ca/4", vec![link("https://a.ca/1", "")])),
+        ];
+        // Page 3 was crawled but has no chunks.
+        let crawled = ["https://a.ca/1", "https://a.ca/3"].map(String::from);
+        let graph = LinkGraph::new(crawled, &chunks, |m| &m.1);
+
+        assert_eq!(graph.orphans(), vec!["https://a.ca/4"]);
+        assert!(graph.broken("https://a.ca/").is_empty());
+        let graph = LinkGraph::new([], &chunks, |m| &m.1);
+        assert_eq!(
+            graph.broken("https://a.ca/"),
+            vec![("https://a.ca/1", "https://a.ca/3")]
+        );
+        assert_eq!(
+            graph
+                .neighbors("https://a.ca/1")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["https://a.ca/2", "https://a.ca/4"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("links.json");
+        graph.save(&path).unwrap();
+        assert_eq!(LinkGraph::load(&path).unwrap(), graph);
+    }
+}
@@ -0,0 +1,608 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chunker::{Chunker, ChunkerConfig},
+    clean::{CleanReport, TextCleaner},
+    Chunk, ChunkMetadata, DocumentFetcher,
+};
+
+/// The kinds of files read by [`LocalFetcher`], by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    /// `.md` and `.markdown` files, split at their headings.
+    Markdown,
+    /// `.txt` files, split at blank lines.
+    Text,
+    /// `.pdf` files, split by page and at lines that look like headings.
+    Pdf,
+}
+
+impl FileKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" => Some(FileKind::Markdown),
+            "txt" => Some(FileKind::Text),
+            "pdf" => Some(FileKind::Pdf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LocalFileMetadata {
+    /// The path of the file, relative to the directory it was read from.
+    pub path: String,
+    pub kind: FileKind,
+    /// The first top-level heading of the file, or its name.
+    pub title: String,
+    /// The page the chunk is on, from 1, for PDFs.
+    #[serde(default)]
+    pub page: Option<u32>,
+    /// The innermost heading the chunk is under, if any.
+    pub heading: Option<String>,
+    /// The title, then every enclosing heading, outermost first.
+    #[serde(default)]
+    pub headings: Vec<String>,
+    /// The level of the innermost heading, or `None` directly under the title.
+    #[serde(default)]
+    pub heading_level: Option<u8>,
+}
+
+impl LocalFileMetadata {
+    /// The heading path, as in "Allaitement > Douleurs".
+    pub fn heading_path(&self) -> String {
+        self.headings.join(" > ")
+    }
+}
+
+impl ChunkMetadata for LocalFileMetadata {
+    fn url(&self) -> &str {
+        &self.path
+    }
+
+    fn heading_path(&self) -> String {
+        self.heading_path()
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some(&self.title)
+    }
+}
+
+/// Why a file could not be read.
+#[derive(Debug)]
+pub enum LocalFileError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Pdf {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl LocalFileError {
+    pub fn path(&self) -> &Path {
+        match self {
+            LocalFileError::Io { path, .. } | LocalFileError::Pdf { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for LocalFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalFileError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            LocalFileError::Pdf { path, reason } => {
+                write!(
+                    f,
+                    "failed to extract text from {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LocalFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LocalFileError::Io { source, .. } => Some(source),
+            LocalFileError::Pdf { .. } => None,
+        }
+    }
+}
+
+/// The outcome of reading a directory that tolerates per-file failures.
+#[derive(Debug)]
+pub struct LocalResult {
+    /// Chunks of every file read successfully, in path order.
+    pub chunks: Vec<Chunk<LocalFileMetadata>>,
+    /// Files that could not be read, and why.
+    pub failures: Vec<LocalFileError>,
+    /// What text cleaning did to the chunks.
+    pub cleaning: CleanReport,
+}
+
+/// Reads the Markdown, text and PDF files of a directory and its subdirectories.
+#[derive(Debug, Clone)]
+pub struct LocalFetcher {
+    dir: PathBuf,
+    chunker: Chunker,
+    cleaner: TextCleaner,
+}
+
+impl LocalFetcher {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            chunker: Chunker::default(),
+            cleaner: TextCleaner::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Sets how parsed paragraphs are merged and split into chunks.
+    pub fn with_chunker(mut self, config: ChunkerConfig) -> Self {
+        self.chunker = Chunker::new(config);
+        self
+    }
+
+    /// Sets how the text of parsed blocks is cleaned before chunking.
+    pub fn with_cleaner(mut self, cleaner: TextCleaner) -> Self {
+        self.cleaner = cleaner;
+        self
+    }
+
+    /// Reads and chunks every supported file. Only failing to list the directory itself is
+    /// fatal; files that cannot be read are reported in [`LocalResult::failures`].
+    ///
+    /// Reading blocks, so async code should call it through `spawn_blocking`, as
+    /// [`DocumentFetcher::fetch`] does.
+    pub fn read(&self) -> Result<LocalResult, LocalFileError> {
+        let mut files = vec![];
+        list_files(&self.dir, &mut files)?;
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut chunks = vec![];
+        let mut failures = vec![];
+        let mut cleaning = CleanReport::default();
+
+        for (path, kind) in files {
+            let relative = path
+                .strip_prefix(&self.dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            tracing::info!("Reading {}", relative);
+
+            let blocks = match read_blocks(&path, kind, &relative) {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    tracing::warn!("Skipping file: {}", e);
+                    failures.push(e);
+                    continue;
+                }
+            };
+
+            let (blocks, report) = self.cleaner.clean(blocks);
+            cleaning.merge(&report);
+            let mut file_chunks = self.chunker.chunk(blocks);
+            Chunk::assign_ids(&mut file_chunks);
+            chunks.extend(file_chunks);
+        }
+
+        Ok(LocalResult {
+            chunks,
+            failures,
+            cleaning,
+        })
+    }
+}
+
+fn list_files(dir: &Path, files: &mut Vec<(PathBuf, FileKind)>) -> Result<(), LocalFileError> {
+    let io_error = |source| LocalFileError::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+
+    for entry in std::fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let path = entry.path();
+        // Unlike `Path::is_dir`, the entry's type does not follow symlinks, which could lead
+        // back to a parent directory.
+        let file_type = entry.file_type().map_err(io_error)?;
+        if file_type.is_dir() {
+            list_files(&path, files)?;
+        } else if file_type.is_symlink() && path.is_dir() {
+            tracing::debug!("Skipping symlinked directory {}", path.display());
+        } else if let Some(kind) = FileKind::from_path(&path) {
+            files.push((path, kind));
+        }
+    }
+    Ok(())
+}
+
+fn read_blocks(
+    path: &Path,
+    kind: FileKind,
+    relative: &str,
+) -> Result<Vec<Chunk<LocalFileMetadata>>, LocalFileError> {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| relative.to_string());
+
+    let read = || {
+        std::fs::read_to_string(path).map_err(|source| LocalFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    };
+
+    Ok(match kind {
+        FileKind::Markdown => parse_markdown(&read()?, relative, &name),
+        FileKind::Text => parse_text(&read()?, relative, &name),
+        FileKind::Pdf => {
+            let pdf_error = |reason: String| LocalFileError::Pdf {
+                path: path.to_path_buf(),
+                reason,
+            };
+            // pdf-extract panics on some fonts and encodings it does not support.
+            let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_by_pages(path))
+                .map_err(|_| pdf_error("unsupported PDF".to_string()))?
+                .map_err(|e| pdf_error(e.to_string()))?;
+            parse_pdf(&pages, relative, &name)
+        }
+    })
+}
+
+/// Builds blocks for one file, keeping track of the headings they are under.
+struct BlockBuilder {
+    metadata: LocalFileMetadata,
+    /// The headings we are currently under, with their level, outermost first.
+    headings: Vec<(u8, String)>,
+    /// The lines of the paragraph being read.
+    lines: Vec<String>,
+    blocks: Vec<Chunk<LocalFileMetadata>>,
+}
+
+impl BlockBuilder {
+    fn new(path: &str, kind: FileKind, title: String) -> Self {
+        Self {
+            metadata: LocalFileMetadata {
+                path: path.to_string(),
+                kind,
+                headings: vec![title.clone()],
+                title,
+                page: None,
+                heading: None,
+                heading_level: None,
+            },
+            headings: vec![],
+            lines: vec![],
+            blocks: vec![],
+        }
+    }
+
+    fn heading(&mut self, level: u8, heading: &str) {
+        self.end_paragraph();
+        self.headings.retain(|(parent, _)| *parent < level);
+        self.headings.push((level, heading.to_string()));
+        self.metadata.heading = Some(heading.to_string());
+        self.metadata.heading_level = Some(level);
+        self.metadata.headings = std::iter::once(self.metadata.title.clone())
+            .chain(self.headings.iter().map(|(_, heading)| heading.clone()))
+            .collect();
+    }
+
+    fn page(&mut self, page: u32) {
+        self.end_paragraph();
+        self.metadata.page = Some(page);
+    }
+
+    fn line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            self.end_paragraph();
+        } else {
+            self.lines.push(line.to_string());
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if !self.lines.is_empty() {
+            let text = std::mem::take(&mut self.lines).join("\n");
+            self.blocks.push(Chunk::new(text, self.metadata.clone()));
+        }
+    }
+
+    fn finish(mut self) -> Vec<Chunk<LocalFileMetadata>> {
+        self.end_paragraph();
+        self.blocks
+    }
+}
+
+/// An ATX heading, as in "## Douleurs", with its level.
+fn markdown_heading(line: &str) -> Option<(u8, &str)> {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[hashes..];
+    if !(1..=6).contains(&hashes) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((hashes as u8, rest.trim().trim_end_matches('#').trim()))
+}
+
+/// Splits Markdown into paragraphs under their headings. The first top-level heading is
+/// taken as the title; code blocks are kept whole.
+fn parse_markdown(markdown: &str, path: &str, name: &str) -> Vec<Chunk<LocalFileMetadata>> {
+    let title = markdown
+        .lines()
+        .find_map(|line| markdown_heading(line).filter(|(level, _)| *level == 1))
+        .map_or_else(|| name.to_string(), |(_, title)| title.to_string());
+
+    let mut builder = BlockBuilder::new(path, FileKind::Markdown, title.clone());
+    let mut in_code = false;
+    let mut title_seen = false;
+
+    for line in markdown.lines() {
+        // A code block is a block of its own, apart from the text just above or below it.
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            if in_code {
+                builder.end_paragraph();
+                builder.lines.push(line.to_string());
+            } else {
+                builder.lines.push(line.to_string());
+                builder.end_paragraph();
+            }
+            continue;
+        }
+        if in_code {
+            builder.lines.push(line.to_string());
+            continue;
+        }
+
+        match markdown_heading(line) {
+            Some((1, heading)) if heading == title && !title_seen => {
+                builder.end_paragraph();
+                title_seen = true;
+            }
+            Some((level, heading)) => builder.heading(level, heading),
+            None => builder.line(line),
+        }
+    }
+
+    builder.finish()
+}
+
+/// Splits plain text into paragraphs at blank lines.
+fn parse_text(text: &str, path: &str, name: &str) -> Vec<Chunk<LocalFileMetadata>> {
+    let mut builder = BlockBuilder::new(path, FileKind::Text, name.to_string());
+    for line in text.lines() {
+        builder.line(line);
+    }
+    builder.finish()
+}
+
+/// Whether a line of text extracted from a PDF looks like a heading: short, capitalized,
+/// not ending like a sentence, and either numbered ("2. Douleurs") or on its own line.
+fn is_pdf_heading(line: &str, previous_blank: bool, next_blank: bool) -> bool {
+    let line = line.trim();
+    let words = line.split_whitespace().count();
+    let Some(first) = line.chars().find(|c| c.is_alphabetic()) else {
+        return false;
+    };
+    let numbered = line.split_once(' ').is_some_and(|(number, _)| {
+        number
+            .trim_end_matches('.')
+            .split('.')
+            .all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    });
+
+    (1..=10).contains(&words)
+        && first.is_uppercase()
+        && !line.ends_with(['.', ',', ';', ':', '!', '?'])
+        && (numbered || (previous_blank && next_blank))
+}
+
+/// Splits the text of each PDF page into paragraphs, under the headings found so far.
+fn parse_pdf(pages: &[String], path: &str, name: &str) -> Vec<Chunk<LocalFileMetadata>> {
+    let mut builder = BlockBuilder::new(path, FileKind::Pdf, name.to_string());
+
+    for (index, page) in pages.iter().enumerate() {
+        builder.page(index as u32 + 1);
+
+        let lines = page.lines().collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let previous_blank = i == 0 || lines[i - 1].trim().is_empty();
+            let next_blank = lines.get(i + 1).is_none_or(|next| next.trim().is_empty());
+            if is_pdf_heading(line, previous_blank, next_blank) {
+                builder.heading(2, line.trim());
+            } else {
+                builder.line(line);
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+impl DocumentFetcher<LocalFileMetadata> for LocalFetcher {
+    async fn fetch(&self) -> Result<Vec<Chunk<LocalFileMetadata>>, Box<dyn std::error::Error>> {
+        let fetcher = self.clone();
+        let result = tokio::task::spawn_blocking(move || fetcher.read()).await??;
+        for failure in &result.failures {
+            tracing::warn!("{}", failure);
+        }
+        Ok(result.chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_markdown, parse_pdf, FileKind, LocalFetcher};
+    use crate::document::{chunker::ChunkerConfig, DocumentFetcher};
+
+    #[test]
+    fn test_parse_markdown() {
+        let markdown = "# Allaitement\n\nIntroduction.\n\n## Douleurs\n\nC'est normal au début.\nÇa passe.\n\n```\n# pas un titre\n```\n\n### Crevasses ##\n\nAppliquez du lait.\n\n## Positions\n\n- Madone\n- Ballon";
+        let blocks = parse_markdown(markdown, "guide.md", "guide");
+
+        let blocks = blocks
+            .iter()
+            .map(|b| (b.metadata.heading_path(), b.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                ("Allaitement".to_string(), "Introduction."),
+                (
+                    "Allaitement > Douleurs".to_string(),
+                    "C'est normal au début.\nÇa passe."
+                ),
+                (
+                    "Allaitement > Douleurs".to_string(),
+                    "```\n# pas un titre\n```"
+                ),
+                (
+                    "Allaitement > Douleurs > Crevasses".to_string(),
+                    "Appliquez du lait."
+                ),
+                ("Allaitement > Positions".to_string(), "- Madone\n- Ballon"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_markdown_code_right_after_text() {
+        let markdown = "Lancez :\n```\nbebe serve\n```\nPuis ouvrez la page.";
+        let blocks = parse_markdown(markdown, "guide.md", "guide");
+
+        let texts = blocks.iter().map(|b| b.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec!["Lancez :", "```\nbebe serve\n```", "Puis ouvrez la page."]
+        );
+    }
+
+    #[test]
+    fn test_parse_pdf() {
+        let pages = vec![
+            "Feuillet de congé\n\n1. Alimentation\nDonnez le sein souvent.\nAu moins 8 fois par jour.\n\nNotes\n\n".to_string(),
+            "Consultez si le bébé boit mal.\n\n2. Sommeil\nSur le dos.".to_string(),
+        ];
+        let blocks = parse_pdf(&pages, "conge.pdf", "conge");
+
+        let blocks = blocks
+            .iter()
+            .map(|b| {
+                (
+                    b.metadata.page,
+                    b.metadata.heading.as_deref(),
+                    b.text.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                (
+                    Some(1),
+                    Some("1. Alimentation"),
+                    "Donnez le sein souvent.\nAu moins 8 fois par jour."
+                ),
+                (Some(2), Some("Notes"), "Consultez si le bébé boit mal."),
+                (Some(2), Some("2. Sommeil"), "Sur le dos."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("feuillets")).unwrap();
+        std::fs::write(
+            dir.path().join("feuillets/sommeil.md"),
+            "# Sommeil\n\nCouchez le bébé sur le dos.",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("aide.txt"),
+            "Info-Santé répond jour et nuit.\n\nComposez le 811.",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("image.png"), [0u8; 4]).unwrap();
+        std::fs::write(dir.path().join("brise.pdf"), "pas un PDF").unwrap();
+
+        let result = LocalFetcher::new(dir.path())
+            .with_chunker(ChunkerConfig {
+                min_tokens: 1,
+                ..Default::default()
+            })
+            .read()
+            .unwrap();
+
+        assert_eq!(result.failures.len(), 1);
+        assert!(result.failures[0].path().ends_with("brise.pdf"));
+
+        let chunks = result
+            .chunks
+            .iter()
+            .map(|c| (c.metadata.path.as_str(), c.metadata.kind, c.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            vec![
+                (
+                    "aide.txt",
+                    FileKind::Text,
+                    "Info-Santé répond jour et nuit.\nComposez le 811."
+                ),
+                (
+                    "feuillets/sommeil.md",
+                    FileKind::Markdown,
+                    "Couchez le bébé sur le dos."
+                ),
+            ]
+        );
+        assert!(result.chunks.iter().all(|c| !c.id.is_empty()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_skip_symlinked_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("aide.txt"), "Composez le 811.").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("boucle")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("aide.txt"), dir.path().join("lien.txt"))
+            .unwrap();
+
+        let chunks = LocalFetcher::new(dir.path())
+            .with_chunker(ChunkerConfig {
+                min_tokens: 1,
+                ..Default::default()
+            })
+            .fetch()
+            .await
+            .unwrap();
+        let paths = chunks
+            .iter()
+            .map(|c| c.metadata.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["aide.txt", "lien.txt"]);
+    }
+}
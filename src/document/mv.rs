@@ -1,24 +1,122 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
-use scraper::{Html, Selector};
+use itertools::Itertools;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Semaphore;
 
-use super::{Chunk, DocumentFetcher};
+use super::{
+    block::{BlockType, Figure},
+    chunker::{Chunker, ChunkerConfig},
+    clean::{CleanReport, TextCleaner},
+    crawler::{CrawlConfig, Crawler, Fetched, PageSource},
+    error::CrawlError,
+    links::{attach_links, Link},
+    snapshot::Snapshot,
+    state::{content_hash, pipeline_fingerprint, CrawlReport, CrawlState, PageState, PageStatus},
+    table::Table,
+    Chunk, ChunkMetadata, CrawlResult, DocumentFetcher,
+};
 
-const ROOT: &str = "https://www.inspq.qc.ca/mieux-vivre/consultez-le-guide";
-const BASE_URL: &str = "https://www.inspq.qc.ca";
-const TEXT_MIN_LENGTH: usize = 42;
+/// The guide's table of contents, where every crawl starts.
+pub const ROOT: &str = "https://www.inspq.qc.ca/mieux-vivre/consultez-le-guide";
 
-pub struct MieuxVivreFetcher {}
+/// Every page of the guide is under this URL.
+pub const GUIDE_URL: &str = "https://www.inspq.qc.ca/mieux-vivre/";
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Bumped whenever [`parse_blocks`] or the chunker changes what it makes of a page, so pages
+/// crawled before are re-chunked.
+const PARSER_VERSION: u32 = 6;
+
+pub struct MieuxVivreFetcher {
+    source: PageSource,
+    config: CrawlConfig,
+    chunker: Arc<Chunker>,
+    cleaner: Arc<TextCleaner>,
+    previous: Arc<PreviousCrawl>,
+}
+
+/// The outcome of a previous crawl, used to skip pages that did not change since.
+#[derive(Debug, Default)]
+struct PreviousCrawl {
+    state: CrawlState,
+    chunks: HashMap<String, Vec<Chunk<MieuxVivreMetadata>>>,
+    /// The pages with chunks, in the order of their chunks.
+    order: Vec<String>,
+}
+
+impl PreviousCrawl {
+    /// The state of a page crawled previously. Its chunks may be none, when all of its
+    /// content was filtered out.
+    fn page(&self, url: &str) -> Option<&PageState> {
+        self.state.pages.get(url)
+    }
+
+    fn chunks(&self, url: &str) -> Vec<Chunk<MieuxVivreMetadata>> {
+        self.chunks.get(url).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct MieuxVivreMetadata {
     pub title: String,
     pub section: String,
     pub subsection: String,
+    /// The innermost heading the chunk is under, if any.
     pub heading: Option<String>,
+    /// The full path to the chunk within its page: the page title, then every enclosing
+    /// h2, h3 and h4, outermost first.
+    #[serde(default)]
+    pub headings: Vec<String>,
+    /// The level of the innermost heading, from 2 to 4, or `None` directly under the title.
+    #[serde(default)]
+    pub heading_level: Option<u8>,
     pub url: String,
+    /// The table this chunk was extracted from, for chunks holding tabular data. The chunk
+    /// text then lists each row as "header : value" pairs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<Table>,
+    /// What the chunk holds: text, a table, a figure, or a callout box.
+    #[serde(default)]
+    pub block_type: BlockType,
+    /// The image this chunk describes, for figure chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub figure: Option<Figure>,
+    /// The links to other pages in the chunk's text, such as "voir la section sur...".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+}
+
+impl MieuxVivreMetadata {
+    /// The heading path, as in "Allaitement > Positions > Position madone".
+    pub fn heading_path(&self) -> String {
+        if self.headings.is_empty() {
+            // Chunks crawled before heading paths were recorded.
+            return std::iter::once(&self.title)
+                .chain(self.heading.as_ref())
+                .join(" > ");
+        }
+        self.headings.join(" > ")
+    }
+}
+
+impl ChunkMetadata for MieuxVivreMetadata {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn heading_path(&self) -> String {
+        self.heading_path()
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some(&self.title)
+    }
 }
 
 #[derive(Debug)]
@@ -28,229 +126,1268 @@ struct MVPageMetadata {
     url: String,
 }
 
+#[derive(Debug)]
+struct CrawledPage {
+    url: String,
+    state: PageState,
+    status: PageStatus,
+    chunks: Vec<Chunk<MieuxVivreMetadata>>,
+    cleaning: CleanReport,
+}
+
+impl Default for MieuxVivreFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MieuxVivreFetcher {
     pub fn new() -> Self {
-        Self {}
+        Self::with_source(PageSource::Live)
+    }
+
+    /// Crawls the live guide and records every page into the snapshot directory.
+    pub fn recording(dir: impl Into<PathBuf>) -> Self {
+        Self::with_source(PageSource::Record(Snapshot::new(dir)))
+    }
+
+    /// Crawls a snapshot directory previously written by [`MieuxVivreFetcher::recording`].
+    pub fn from_snapshot(dir: impl Into<PathBuf>) -> Self {
+        Self::with_source(PageSource::Replay(Snapshot::new(dir)))
+    }
+
+    pub fn with_source(source: PageSource) -> Self {
+        Self {
+            source,
+            config: CrawlConfig::default(),
+            chunker: Arc::default(),
+            cleaner: Arc::default(),
+            previous: Arc::default(),
+        }
+    }
+
+    /// Sets the retry, rate limiting and robots.txt behaviour of the crawl.
+    pub fn with_config(mut self, config: CrawlConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets how parsed paragraphs are merged and split into chunks.
+    pub fn with_chunker(mut self, config: ChunkerConfig) -> Self {
+        self.chunker = Arc::new(Chunker::new(config));
+        self
+    }
+
+    /// Sets how the text of parsed blocks is cleaned before chunking.
+    pub fn with_cleaner(mut self, cleaner: TextCleaner) -> Self {
+        self.cleaner = Arc::new(cleaner);
+        self
+    }
+
+    /// Makes the crawl incremental: pages that did not change since the crawl that produced
+    /// `state` and `chunks` are not re-chunked, and their previous chunks are reused.
+    pub fn with_previous(
+        mut self,
+        state: CrawlState,
+        chunks: Vec<Chunk<MieuxVivreMetadata>>,
+    ) -> Self {
+        let mut by_url: HashMap<String, Vec<Chunk<MieuxVivreMetadata>>> = HashMap::new();
+        let mut order = vec![];
+        for chunk in chunks {
+            if !by_url.contains_key(&chunk.metadata.url) {
+                order.push(chunk.metadata.url.clone());
+            }
+            by_url
+                .entry(chunk.metadata.url.clone())
+                .or_default()
+                .push(chunk);
+        }
+        for chunks in by_url.values_mut() {
+            // Chunks saved before chunk IDs existed.
+            if chunks.iter().any(|chunk| chunk.id.is_empty()) {
+                Chunk::assign_ids(chunks);
+            }
+        }
+
+        self.previous = Arc::new(PreviousCrawl {
+            state,
+            chunks: by_url,
+            order,
+        });
+        self
+    }
+
+    /// Crawls and chunks the whole guide.
+    ///
+    /// Only a failure to load the guide's root page aborts the crawl. Sections and pages
+    /// that fail are reported in [`CrawlResult::failures`] and skipped, though pages that
+    /// were crawled previously keep their previous chunks.
+    ///
+    /// Chunks come out in the order pages appear in the guide's menus, and in page order
+    /// within a page, so the same guide always gives the same output.
+    pub async fn crawl(&self) -> Result<CrawlResult<MieuxVivreMetadata>, CrawlError> {
+        tracing::info!("Crawling and chunking Mieux Vivre");
+
+        let crawler = Arc::new(Crawler::new(self.source.clone(), self.config.clone()));
+
+        let response = crawler.get(ROOT).await?;
+
+        // Fetch all main sections pages
+        let (sections, mut failures) = parse_sections(&response, ROOT);
+        for e in &failures {
+            tracing::warn!("Skipping section: {}", e);
+        }
+
+        tracing::debug!("Found sections: {:#?}", sections);
+
+        let mut pages = vec![];
+
+        for (section_title, url) in sections {
+            tracing::info!("Crawling {}", url);
+
+            let section_pages = match crawler.get(&url).await {
+                Ok(response) => parse_section_menu(&response, &url, &section_title),
+                Err(e) => Err(e),
+            };
+
+            match section_pages {
+                Ok(section_pages) => pages.extend(section_pages),
+                Err(e) => {
+                    tracing::warn!("Skipping section {}: {}", section_title, e);
+                    failures.push(e);
+                }
+            }
+        }
+
+        // A page listed under several menus is crawled once, under the first one.
+        let mut listed = HashSet::new();
+        pages.retain(|page| listed.insert(page.url.clone()));
+
+        tracing::debug!("Found pages: {:#?}", pages);
+
+        // When a section could not be crawled, we cannot tell its pages apart from removed ones.
+        let sections_complete = failures.is_empty();
+        let discovered = pages
+            .iter()
+            .map(|page| page.url.clone())
+            .collect::<HashSet<_>>();
+
+        let urls = pages
+            .iter()
+            .map(|page| page.url.clone())
+            .collect::<Vec<_>>();
+
+        // Chunks of every page, by the order in which the page was discovered.
+        let mut page_chunks = vec![];
+        let mut page_failures = vec![];
+        let mut state = CrawlState::default();
+        let mut report = CrawlReport::default();
+        let mut cleaning = CleanReport::default();
+
+        let mut set = tokio::task::JoinSet::new();
+
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(self.config.max_concurrency));
+        let fingerprint: Arc<str> =
+            pipeline_fingerprint(PARSER_VERSION, &self.cleaner, self.chunker.config()).into();
+
+        for (index, page) in pages.into_iter().enumerate() {
+            let crawler = crawler.clone();
+            let chunker = self.chunker.clone();
+            let cleaner = self.cleaner.clone();
+            let previous = self.previous.clone();
+            let semaphore = semaphore.clone();
+            let fingerprint = fingerprint.clone();
+
+            set.spawn(async move {
+                let result = MieuxVivreFetcher::get_page_content(
+                    &crawler,
+                    &chunker,
+                    &cleaner,
+                    &fingerprint,
+                    &previous,
+                    &page,
+                    semaphore,
+                )
+                .await;
+                (index, result)
+            });
+        }
+
+        // Pages complete in any order, so they are put back in discovery order afterwards.
+        while let Some(res) = set.join_next().await {
+            let (index, result) = res.expect("page crawl task panicked");
+            match result {
+                Ok(page) => {
+                    report.record(&page.url, page.status);
+                    state.pages.insert(page.url, page.state);
+                    cleaning.merge(&page.cleaning);
+                    page_chunks.push((index, page.chunks));
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping page: {}", e);
+                    page_chunks.push((index, self.keep_previous(e.url(), &mut state)));
+                    page_failures.push((index, e));
+                }
+            }
+        }
+
+        page_chunks.sort_by_key(|(index, _)| *index);
+        page_failures.sort_by_key(|(index, _)| *index);
+        let mut page_chunks = page_chunks
+            .into_iter()
+            .map(|(index, chunks)| (urls[index].clone(), chunks))
+            .collect::<Vec<_>>();
+        failures.extend(page_failures.into_iter().map(|(_, e)| e));
+
+        let mut kept = HashMap::new();
+        for url in self.previous.state.pages.keys() {
+            if discovered.contains(url) {
+                continue;
+            }
+
+            if sections_complete {
+                report.removed.push(url.clone());
+            } else {
+                kept.insert(url.clone(), self.keep_previous(url, &mut state));
+            }
+        }
+        restore_order(&self.previous.order, &mut page_chunks, kept);
+        let chunks = page_chunks
+            .into_iter()
+            .flat_map(|(_, chunks)| chunks)
+            .collect::<Vec<_>>();
+
+        report.added.sort();
+        report.changed.sort();
+        report.removed.sort();
+
+        Ok(CrawlResult {
+            chunks,
+            failures,
+            state,
+            report,
+            cleaning,
+        })
+    }
+
+    /// Carries a page over from the previous crawl, when it could not be crawled this time,
+    /// returning its previous chunks.
+    fn keep_previous(&self, url: &str, state: &mut CrawlState) -> Vec<Chunk<MieuxVivreMetadata>> {
+        match self.previous.page(url) {
+            Some(page_state) => {
+                state.pages.insert(url.to_string(), page_state.clone());
+                self.previous.chunks(url)
+            }
+            None => vec![],
+        }
     }
 
+    /// Fetches and chunks a page. Pages that did not change since the previous crawl, and
+    /// were chunked with the same `fingerprint`, keep their previous chunks.
     async fn get_page_content(
-        client: &reqwest::Client,
-        url: &str,
-        section: &str,
-        subsection: &str,
+        crawler: &Crawler,
+        chunker: &Chunker,
+        cleaner: &TextCleaner,
+        fingerprint: &str,
+        previous: &PreviousCrawl,
+        page: &MVPageMetadata,
         semaphore: Arc<Semaphore>,
-    ) -> Result<Vec<Chunk<MieuxVivreMetadata>>, reqwest::Error> {
+    ) -> Result<CrawledPage, CrawlError> {
         let _permit = semaphore.acquire().await;
 
-        tracing::info!("Fetching {}", url);
+        tracing::info!("Fetching {}", page.url);
 
-        let mut chunks = vec![];
+        let previous_state = previous.page(&page.url);
+        // Pages chunked differently are fetched in full to be re-chunked, changed or not.
+        let reusable = previous_state.filter(|state| state.fingerprint == fingerprint);
 
-        let response = client.get(url).send().await?;
-        let document = Html::parse_document(&response.text().await?);
-        let selector = Selector::parse(".two-column-layout__left .field__item").unwrap();
-        let title_selector = Selector::parse("h1").unwrap();
+        let (body, etag, last_modified) = match crawler.get_if_modified(&page.url, reusable).await?
+        {
+            Fetched::Page {
+                body,
+                etag,
+                last_modified,
+            } => (body, etag, last_modified),
+            Fetched::NotModified => {
+                tracing::debug!("{} was not modified", page.url);
+                return Ok(CrawledPage {
+                    url: page.url.clone(),
+                    state: reusable.cloned().unwrap_or_default(),
+                    status: PageStatus::Unchanged,
+                    chunks: previous.chunks(&page.url),
+                    cleaning: CleanReport::default(),
+                });
+            }
+        };
 
-        let title = document.select(&title_selector).next().unwrap();
-        let title = title.text().collect::<String>();
+        let document = Html::parse_document(&body);
+        let state = PageState {
+            etag,
+            last_modified,
+            content_hash: hash_page(&document, &page.url)?,
+            fingerprint: fingerprint.to_string(),
+        };
 
-        let content = document.select(&selector).next().unwrap();
+        let unchanged =
+            reusable.is_some_and(|reusable| reusable.content_hash == state.content_hash);
+        let (status, chunks, cleaning) = if unchanged {
+            (
+                PageStatus::Unchanged,
+                previous.chunks(&page.url),
+                CleanReport::default(),
+            )
+        } else {
+            // A page re-chunked with other settings is reported as changed, as its chunks are.
+            let status = match previous_state {
+                Some(_) => PageStatus::Changed,
+                None => PageStatus::Added,
+            };
+            let blocks = parse_blocks(
+                &document,
+                &page.url,
+                &page.section,
+                page.subsection.as_deref().unwrap_or_default(),
+                chunker.config().max_tokens,
+            )?;
+            let (mut blocks, cleaning) = cleaner.clean(blocks);
+            let links = blocks
+                .iter_mut()
+                .map(|block| std::mem::take(&mut block.metadata.links))
+                .collect::<Vec<_>>();
+            let chunks = chunker.chunk_with_sources(blocks);
+            let mut chunks = attach_links(chunks, &links, cleaner, |m| &mut m.links);
+            Chunk::assign_ids(&mut chunks);
+            (status, chunks, cleaning)
+        };
 
-        let mut current_heading = None;
+        Ok(CrawledPage {
+            url: page.url.clone(),
+            state,
+            status,
+            chunks,
+            cleaning,
+        })
+    }
+}
 
-        content.child_elements().for_each(|element| {
-            // Mieux Vivre uses h2 for headings within a page. We use this to determine the current heading.
-            // Most of the elements within the main content are p tags. Sometimes,
-            // a sub div is used for things like call outs. We grab the text from those sub divs as a chunk.
+/// Puts the pages kept from the previous crawl back where they were in it, each after the
+/// page it followed then, so the order of the chunks does not depend on what failed.
+fn restore_order(
+    previous_order: &[String],
+    pages: &mut Vec<(String, Vec<Chunk<MieuxVivreMetadata>>)>,
+    mut kept: HashMap<String, Vec<Chunk<MieuxVivreMetadata>>>,
+) {
+    let mut at = 0;
+    for url in previous_order {
+        if let Some(position) = pages.iter().position(|(page, _)| page == url) {
+            at = position + 1;
+        } else if let Some(chunks) = kept.remove(url) {
+            pages.insert(at, (url.clone(), chunks));
+            at += 1;
+        }
+    }
+}
 
-            // When we find a UL, we add it to the previous chunk.
+/// Resolves a link found on `page_url` into an absolute URL.
+fn resolve_link(page_url: &str, href: &str) -> Result<String, CrawlError> {
+    Url::parse(page_url)
+        .and_then(|base| base.join(href))
+        .map(String::from)
+        .map_err(|e| CrawlError::MalformedMenu {
+            url: page_url.to_string(),
+            reason: format!("invalid link {:?}: {}", href, e),
+        })
+}
 
-            if element.value().name() == "h2"
-                || element.value().name() == "h3"
-                || element.value().name() == "h4"
-            {
-                current_heading = Some(element.text().collect::<String>());
-            } else if element.value().name() == "p" {
-                let text = element.text().collect::<String>();
-                chunks.push(Chunk {
-                    text,
-                    metadata: MieuxVivreMetadata {
-                        title: title.clone(),
-                        section: section.to_string(),
-                        subsection: subsection.to_string(),
-                        heading: current_heading.clone(),
-                        url: url.to_string(),
+/// Finds the `href` of the first `a` child of a menu item.
+fn menu_link<'a>(item: ElementRef<'a>) -> Option<(String, &'a str)> {
+    item.child_elements()
+        .find(|e| e.value().name() == "a")
+        .and_then(|a| Some((a.text().collect::<String>(), a.value().attr("href")?)))
+}
+
+/// Parses the guide's root page into `(section title, absolute url)` pairs. Malformed
+/// section links are skipped and returned as failures.
+fn parse_sections(html: &str, url: &str) -> (Vec<(String, String)>, Vec<CrawlError>) {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(".carte-lien-mv").unwrap();
+
+    document
+        .select(&selector)
+        .map(|element| {
+            let link = element
+                .first_child()
+                .and_then(|link| {
+                    let href = link.value().as_element()?.attr("href")?;
+                    let title = link
+                        .children()
+                        .nth(1)?
+                        .first_child()?
+                        .value()
+                        .as_text()?
+                        .to_string();
+                    Some((title, href))
+                })
+                .ok_or_else(|| CrawlError::MalformedMenu {
+                    url: url.to_string(),
+                    reason: "section link without href or title".to_string(),
+                })?;
+
+            Ok((link.0, resolve_link(url, link.1)?))
+        })
+        .partition_result()
+}
+
+/// Parses a section page's side menu into the pages it lists, including nested pages.
+fn parse_section_menu(
+    html: &str,
+    url: &str,
+    section_title: &str,
+) -> Result<Vec<MVPageMetadata>, CrawlError> {
+    let malformed = |reason: &str| CrawlError::MalformedMenu {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("#block-mieuxvivre-post-content-menu .menu").unwrap();
+    let ul = document
+        .select(&selector)
+        .next()
+        .ok_or_else(|| malformed("no post content menu"))?;
+
+    let mut pages = vec![];
+
+    for element in ul.child_elements() {
+        let (subsection_title, href) =
+            menu_link(element).ok_or_else(|| malformed("menu item without a link"))?;
+
+        pages.push(MVPageMetadata {
+            section: section_title.to_string(),
+            subsection: Some(subsection_title.clone()),
+            url: resolve_link(url, href)?,
+        });
+
+        if let Some(element) = element.child_elements().find(|e| e.value().name() == "ul") {
+            for element in element.child_elements() {
+                if let Some((_, href)) = menu_link(element) {
+                    pages.push(MVPageMetadata {
+                        section: section_title.to_string(),
+                        subsection: Some(subsection_title.clone()),
+                        url: resolve_link(url, href)?,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Finds a content page's title and main content container.
+fn page_content<'a>(
+    document: &'a Html,
+    url: &str,
+) -> Result<(ElementRef<'a>, ElementRef<'a>), CrawlError> {
+    let selector = Selector::parse(".two-column-layout__left .field__item").unwrap();
+    let title_selector = Selector::parse("h1").unwrap();
+
+    let title =
+        document
+            .select(&title_selector)
+            .next()
+            .ok_or_else(|| CrawlError::MissingTitle {
+                url: url.to_string(),
+            })?;
+
+    let content = document
+        .select(&selector)
+        .next()
+        .ok_or_else(|| CrawlError::MissingContent {
+            url: url.to_string(),
+        })?;
+
+    Ok((title, content))
+}
+
+/// Hashes the parts of a content page we chunk, ignoring the layout around them.
+fn hash_page(document: &Html, url: &str) -> Result<String, CrawlError> {
+    let (title, content) = page_content(document, url)?;
+    Ok(content_hash(&(title.html() + &content.html())))
+}
+
+/// Parses a content page into blocks: paragraphs, lists and callouts.
+#[cfg(test)]
+fn parse_page(
+    html: &str,
+    url: &str,
+    section: &str,
+    subsection: &str,
+) -> Result<Vec<Chunk<MieuxVivreMetadata>>, CrawlError> {
+    parse_blocks(
+        &Html::parse_document(html),
+        url,
+        section,
+        subsection,
+        ChunkerConfig::default().max_tokens,
+    )
+}
+
+/// Parses a content page into blocks, each with the links found in the element it comes
+/// from. Those links are taken out before chunking and attached back, see [`attach_links`].
+///
+/// Tables larger than `max_tokens` are split by rows, so that no chunk carries more of a
+/// table than its text holds.
+fn parse_blocks(
+    document: &Html,
+    url: &str,
+    section: &str,
+    subsection: &str,
+    max_tokens: usize,
+) -> Result<Vec<Chunk<MieuxVivreMetadata>>, CrawlError> {
+    let mut chunks: Vec<Chunk<MieuxVivreMetadata>> = vec![];
+
+    let (title, content) = page_content(document, url)?;
+    let title = title.text().collect::<String>().trim().to_string();
+
+    let table_selector = Selector::parse("table").unwrap();
+    let img_selector = Selector::parse("img").unwrap();
+
+    // The headings we are currently under, with their level, outermost first.
+    let mut headings: Vec<(u8, String)> = vec![];
+
+    let metadata = |headings: &[(u8, String)]| MieuxVivreMetadata {
+        title: title.clone(),
+        section: section.to_string(),
+        subsection: subsection.to_string(),
+        heading: headings.last().map(|(_, heading)| heading.clone()),
+        headings: std::iter::once(title.clone())
+            .chain(headings.iter().map(|(_, heading)| heading.clone()))
+            .collect(),
+        heading_level: headings.last().map(|(level, _)| *level),
+        url: url.to_string(),
+        table: None,
+        block_type: BlockType::Text,
+        figure: None,
+        links: vec![],
+    };
+    let table_chunks = |table: Table, headings: &[(u8, String)]| {
+        table
+            .split(max_tokens)
+            .into_iter()
+            .map(|table| {
+                Chunk::new(
+                    table.to_text(),
+                    MieuxVivreMetadata {
+                        table: Some(table),
+                        block_type: BlockType::Table,
+                        ..metadata(headings)
                     },
-                });
-            } else if element.value().name() == "div" || element.value().name() == "article" {
-                current_heading = None;
-                let text = element.text().collect::<String>();
-                chunks.push(Chunk {
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let figure_chunk = |mut figure: Figure, headings: &[(u8, String)]| {
+        figure.src = figure.src.and_then(|src| resolve_link(url, &src).ok());
+        Chunk::new(
+            figure.to_text(),
+            MieuxVivreMetadata {
+                figure: Some(figure),
+                block_type: BlockType::Figure,
+                ..metadata(headings)
+            },
+        )
+    };
+    // Images within paragraphs, with the alt text that describes them.
+    let inline_figures = |element: ElementRef, headings: &[(u8, String)]| {
+        element
+            .select(&img_selector)
+            .filter_map(Figure::parse)
+            .map(|figure| figure_chunk(figure, headings))
+            .collect::<Vec<_>>()
+    };
+
+    content.child_elements().for_each(|element| {
+        // Mieux Vivre uses h2 to h4 for headings within a page. We keep track of the headings we
+        // are under so every block knows its full path within the page.
+        // Most of the elements within the main content are p tags. Sometimes,
+        // a sub div is used for things like call outs. We grab the text from those sub divs as a block.
+
+        // Lists become their own block, which the chunker merges with the paragraph introducing them.
+        // Tables, sometimes wrapped in a div, become blocks of their own that keep every value
+        // next to its header, and carry the parsed table in their metadata.
+        // Figures, callout boxes, warnings and "À retenir" summaries become blocks of their own
+        // too, marked with their block type.
+
+        let name = element.value().name();
+        let start = chunks.len();
+
+        if let Some(level) = heading_level(name) {
+            headings.retain(|(parent, _)| *parent < level);
+            headings.push((level, element.text().collect::<String>().trim().to_string()));
+        } else if name == "table" {
+            if let Some(table) = Table::parse(element) {
+                chunks.extend(table_chunks(table, &headings));
+            }
+        } else if name == "figure" || name == "img" {
+            if let Some(figure) = Figure::parse(element) {
+                chunks.push(figure_chunk(figure, &headings));
+            }
+        } else if matches!(name, "div" | "article" | "aside")
+            && element.select(&table_selector).next().is_some()
+        {
+            // Tables come first so a callout holding a table still yields the table; the
+            // text around it keeps the callout's type.
+            let text = text_outside_tables(element);
+            if !text.trim().is_empty() {
+                chunks.push(Chunk::new(
                     text,
-                    metadata: MieuxVivreMetadata {
-                        title: title.clone(),
-                        section: section.to_string(),
-                        subsection: subsection.to_string(),
-                        heading: current_heading.clone(),
-                        url: url.to_string(),
+                    MieuxVivreMetadata {
+                        block_type: BlockType::of_callout(element).unwrap_or_default(),
+                        ..metadata(&headings)
                     },
-                });
-            } else if element.value().name() == "ul" || element.value().name() == "ol" {
-                let text = element.text().collect::<String>();
-                match chunks.last_mut() {
-                    Some(last_chunk) => {
-                        last_chunk.text.push_str("\n");
-                        last_chunk.text.push_str(&text);
-                    }
-                    None => {
-                        chunks.push(Chunk {
-                            text,
-                            metadata: MieuxVivreMetadata {
-                                title: title.clone(),
-                                section: section.to_string(),
-                                subsection: subsection.to_string(),
-                                heading: current_heading.clone(),
-                                url: url.to_string(),
-                            },
-                        });
-                    }
+                ));
+            }
+            for table in element.select(&table_selector) {
+                // Nested tables are part of the table containing them.
+                let nested = table
+                    .ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .take_while(|ancestor| *ancestor != element)
+                    .any(|ancestor| ancestor.value().name() == "table");
+                if let Some(table) = Table::parse(table).filter(|_| !nested) {
+                    chunks.extend(table_chunks(table, &headings));
                 }
-            } else {
-                tracing::warn!("Unknown element: {:?}", element.value().name());
             }
-        });
+        } else if let Some(block_type) = (matches!(name, "div" | "article" | "aside"))
+            .then(|| BlockType::of_callout(element))
+            .flatten()
+        {
+            chunks.push(Chunk::new(
+                block_text(element),
+                MieuxVivreMetadata {
+                    block_type,
+                    ..metadata(&headings)
+                },
+            ));
+        } else if matches!(name, "p" | "div" | "article" | "aside") {
+            let text = element.text().collect::<String>();
+            chunks.push(Chunk::new(text, metadata(&headings)));
+            chunks.extend(inline_figures(element, &headings));
+        } else if name == "ul" || name == "ol" {
+            let text = element
+                .child_elements()
+                .map(|item| format!("- {}", item.text().collect::<String>().trim()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            chunks.push(Chunk::new(text, metadata(&headings)));
+        } else {
+            tracing::warn!("Unknown element: {:?}", name);
+        }
+
+        // The links of the element go with the blocks made from it, except the figures.
+        let links = Link::parse(element, url);
+        for chunk in &mut chunks[start..] {
+            if chunk.metadata.block_type != BlockType::Figure {
+                chunk.metadata.links = links.clone();
+            }
+        }
+    });
+
+    Ok(chunks)
+}
+
+/// The text of a callout box, with one line per paragraph, heading or list item, and list
+/// items marked with "- ".
+fn block_text(element: ElementRef) -> String {
+    fn visit(element: ElementRef, lines: &mut Vec<String>, current: &mut String) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                current.push_str(text);
+                continue;
+            }
+            let Some(child) = ElementRef::wrap(child) else {
+                continue;
+            };
+
+            let name = child.value().name();
+            let block = matches!(name, "p" | "div" | "li" | "ul" | "ol" | "br")
+                || heading_level(name).is_some()
+                || name == "h1";
+            if !block {
+                visit(child, lines, current);
+                continue;
+            }
 
-        Ok(chunks)
+            end_line(lines, current);
+            if name == "li" {
+                current.push_str("- ");
+            }
+            visit(child, lines, current);
+            end_line(lines, current);
+        }
+    }
+
+    fn end_line(lines: &mut Vec<String>, current: &mut String) {
+        let line = current.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() && line != "-" {
+            lines.push(line);
+        }
+        current.clear();
+    }
+
+    let mut lines = vec![];
+    let mut current = String::new();
+    visit(element, &mut lines, &mut current);
+    end_line(&mut lines, &mut current);
+    lines.join("\n")
+}
+
+/// The text of an element, leaving out any table within it.
+fn text_outside_tables(element: ElementRef) -> String {
+    element
+        .descendants()
+        .filter(|node| {
+            !node
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .take_while(|ancestor| *ancestor != element)
+                .any(|ancestor| ancestor.value().name() == "table")
+        })
+        .filter_map(|node| node.value().as_text())
+        .map(|text| &**text)
+        .collect()
+}
+
+/// The level of a heading element within a page. The page title is the only h1.
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        _ => None,
     }
 }
 
 impl DocumentFetcher<MieuxVivreMetadata> for MieuxVivreFetcher {
     async fn fetch(&self) -> Result<Vec<Chunk<MieuxVivreMetadata>>, Box<dyn std::error::Error>> {
-        tracing::info!("Crawling and chunking Mieux Vivre");
+        let result = self.crawl().await?;
+        if !result.failures.is_empty() {
+            tracing::warn!("{} pages failed to crawl", result.failures.len());
+        }
+        Ok(result.chunks)
+    }
+}
 
-        let client = reqwest::Client::builder().build()?;
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
 
-        let response = client.get(ROOT).send().await?.text().await?;
+    use super::{
+        parse_page, parse_section_menu, parse_sections, MieuxVivreFetcher, MieuxVivreMetadata,
+    };
+    use crate::document::{
+        block::BlockType, chunker::ChunkerConfig, error::CrawlError, links::Link,
+        snapshot::Snapshot, state::CrawlState, DocumentFetcher,
+    };
 
-        let document = Html::parse_document(&response);
+    const ROOT_HTML: &str = r#"<html><body>
+        <div class="carte-lien-mv"><a href="/mieux-vivre/grossesse"><img src="g.jpg"><span>Grossesse</span></a></div>
+    </body></html>"#;
 
-        let selector = Selector::parse(".carte-lien-mv").unwrap();
+    const SECTION_HTML: &str = r#"<html><body>
+        <nav id="block-mieuxvivre-post-content-menu"><ul class="menu">
+            <li><a href="/mieux-vivre/grossesse/les-etapes">Les étapes avant la grossesse</a>
+                <ul><li><a href="/mieux-vivre/grossesse/les-etapes/suivi">Le suivi de grossesse</a></li></ul>
+            </li>
+        </ul></nav>
+    </body></html>"#;
 
-        // Fetch all main sections pages
-        let sections = document.select(&selector).map(|element| {
-            element
-                .first_child()
-                .map(|element| {
-                    let href = element
-                        .value()
-                        .as_element()
-                        .map(|element| element.attr("href").unwrap())
-                        .unwrap();
+    const PAGE_HTML: &str = r#"<html><body>
+        <h1>Les étapes de la grossesse</h1>
+        <div class="two-column-layout__left"><div class="field__item">
+            <p>La grossesse est un événement qui entraîne toute une série de mécanismes.</p>
+            <h2>Le premier trimestre</h2>
+            <p>Le premier trimestre couvre les 13 premières semaines de la grossesse. Voir
+                <a href="/mieux-vivre/grossesse/les-etapes/suivi#visites">le suivi de grossesse</a>.</p>
+            <ul><li>Nausées</li><li>Fatigue</li></ul>
+        </div></div>
+    </body></html>"#;
 
-                    let title = element
-                        .children()
-                        .skip(1)
-                        .next()
-                        .map(|element| {
-                            element
-                                .first_child()
-                                .map(|element| element.value().as_text().unwrap().text.to_string())
-                                .unwrap()
-                        })
-                        .unwrap();
-
-                    (title, href)
-                })
-                .expect("Failed to parse section link")
-        });
+    const SECTION_URL: &str = "https://www.inspq.qc.ca/mieux-vivre/grossesse";
+    const PAGE_URLS: [&str; 2] = [
+        "https://www.inspq.qc.ca/mieux-vivre/grossesse/les-etapes",
+        "https://www.inspq.qc.ca/mieux-vivre/grossesse/les-etapes/suivi",
+    ];
 
-        tracing::debug!("Found sections: {:#?}", sections);
+    async fn write_snapshot(snapshot: &Snapshot, pages: [&str; 2]) {
+        snapshot.write(super::ROOT, ROOT_HTML).await.unwrap();
+        snapshot.write(SECTION_URL, SECTION_HTML).await.unwrap();
+        for (url, html) in PAGE_URLS.into_iter().zip(pages) {
+            snapshot.write(url, html).await.unwrap();
+        }
+    }
 
-        let mut pages = vec![];
+    #[test]
+    fn test_parse_sections() {
+        let (sections, failures) = parse_sections(ROOT_HTML, super::ROOT);
+        assert_eq!(
+            sections,
+            vec![("Grossesse".to_string(), SECTION_URL.to_string())]
+        );
+        assert!(failures.is_empty());
 
-        for (section_title, href) in sections {
-            tracing::info!("Crawling {}", href);
-
-            let response = client
-                .get(format!("{}{}", BASE_URL, href))
-                .send()
-                .await?
-                .text()
-                .await?;
-
-            let document = Html::parse_document(&response);
-            let selector = Selector::parse("#block-mieuxvivre-post-content-menu .menu").unwrap();
-            let ul = document.select(&selector).next().unwrap();
-            ul.child_elements().for_each(|element| {
-                let (subsection_title, href) = element
-                    .child_elements()
-                    .find(|e| e.value().name() == "a")
-                    .map(|element| {
-                        let href = element.value().attr("href").unwrap();
-                        let title = element.text().collect::<String>();
-                        (title, href.to_string())
-                    })
-                    .unwrap();
-
-                pages.push(MVPageMetadata {
-                    section: section_title.clone(),
-                    subsection: Some(subsection_title.clone()),
-                    url: format!("{}{}", BASE_URL, href),
-                });
+        // A malformed section link is skipped, and the others are still crawled.
+        let html = ROOT_HTML.replace(
+            "<div class=\"carte-lien-mv\">",
+            "<div class=\"carte-lien-mv\"><span>Sans lien</span></div><div class=\"carte-lien-mv\">",
+        );
+        let (sections, failures) = parse_sections(&html, super::ROOT);
+        assert_eq!(sections.len(), 1);
+        assert!(matches!(
+            failures.as_slice(),
+            [CrawlError::MalformedMenu { url, .. }] if url == super::ROOT
+        ));
+    }
 
-                element
-                    .child_elements()
-                    .find(|e| e.value().name() == "ul")
-                    .map(|element| {
-                        element.child_elements().for_each(|element| {
-                            element
-                                .child_elements()
-                                .find(|e| e.value().name() == "a")
-                                .map(|element| {
-                                    let href = element.value().attr("href").unwrap();
-                                    let page_title = element.text().collect::<String>();
-                                    let url = format!("{}{}", BASE_URL, href);
-
-                                    pages.push(MVPageMetadata {
-                                        section: section_title.clone(),
-                                        subsection: Some(subsection_title.clone()),
-                                        url,
-                                    });
-                                });
-                        });
-                    });
-            });
-        }
+    #[test]
+    fn test_parse_section_menu() {
+        let pages = parse_section_menu(SECTION_HTML, SECTION_URL, "Grossesse").unwrap();
+        let urls = pages.iter().map(|p| p.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(urls, PAGE_URLS);
+        assert!(pages
+            .iter()
+            .all(|p| p.subsection.as_deref() == Some("Les étapes avant la grossesse")));
+    }
 
-        tracing::debug!("Found pages: {:#?}", pages);
+    #[test]
+    fn test_parse_page() {
+        let chunks = parse_page(PAGE_HTML, "https://example.com", "Grossesse", "Étapes").unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].metadata.heading, None);
+        assert_eq!(chunks[0].metadata.title, "Les étapes de la grossesse");
+        assert_eq!(
+            chunks[1].metadata.heading.as_deref(),
+            Some("Le premier trimestre")
+        );
+        assert_eq!(chunks[2].text, "- Nausées\n- Fatigue");
+        // Blocks carry the links of their own element only.
+        assert_eq!(
+            chunks[1].metadata.links,
+            vec![Link {
+                url: "https://example.com/mieux-vivre/grossesse/les-etapes/suivi".to_string(),
+                text: "le suivi de grossesse".to_string(),
+            }]
+        );
+        assert!(chunks[2].metadata.links.is_empty());
+        assert_eq!(
+            chunks[2].metadata,
+            MieuxVivreMetadata {
+                links: vec![],
+                ..chunks[1].metadata.clone()
+            }
+        );
+    }
 
-        let mut chunks = vec![];
+    #[test]
+    fn test_heading_path() {
+        let html = r#"<h1>Allaitement</h1>
+            <div class="two-column-layout__left"><div class="field__item">
+                <h2>Positions</h2>
+                <h3>Position madone</h3>
+                <p>Tenez le bébé face à vous.</p>
+                <div>Important : changez de position.</div>
+                <h4>Variante</h4>
+                <p>Avec un coussin.</p>
+                <h3>Position ballon de football</h3>
+                <p>Le bébé est sous votre bras.</p>
+                <h2>Durée</h2>
+                <p>Variable.</p>
+            </div></div>"#;
 
-        let mut set = tokio::task::JoinSet::new();
+        let chunks = parse_page(html, "https://example.com", "", "").unwrap();
+        let paths = chunks
+            .iter()
+            .map(|c| (c.metadata.heading_path(), c.metadata.heading_level))
+            .collect::<Vec<_>>();
 
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(25));
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "Allaitement > Positions > Position madone".to_string(),
+                    Some(3)
+                ),
+                (
+                    "Allaitement > Positions > Position madone".to_string(),
+                    Some(3)
+                ),
+                (
+                    "Allaitement > Positions > Position madone > Variante".to_string(),
+                    Some(4)
+                ),
+                (
+                    "Allaitement > Positions > Position ballon de football".to_string(),
+                    Some(3)
+                ),
+                ("Allaitement > Durée".to_string(), Some(2)),
+            ]
+        );
+        assert_eq!(chunks[4].metadata.heading.as_deref(), Some("Durée"));
+    }
 
-        for page in pages {
-            let client = client.clone();
-            let section = page.section.clone();
-            let subsection = page.subsection.clone().unwrap_or_else(|| "".to_string());
-            let url = page.url.clone();
-            let semaphore = semaphore.clone();
+    #[test]
+    fn test_parse_tables() {
+        let html = r#"<h1>Alimentation</h1>
+            <div class="two-column-layout__left"><div class="field__item">
+                <h2>Quantités</h2>
+                <p>Voici des repères.</p>
+                <table>
+                    <tr><th>Âge</th><th>Boires par jour</th></tr>
+                    <tr><td>1 mois</td><td>6 à 8</td></tr>
+                </table>
+                <div class="table-responsive">
+                    <p>Calendrier :</p>
+                    <table><tr><td>2 mois</td><td>Rotavirus</td></tr></table>
+                </div>
+            </div></div>"#;
 
-            set.spawn(async move {
-                MieuxVivreFetcher::get_page_content(&client, &url, &section, &subsection, semaphore)
-                    .await
-            });
-        }
+        let chunks = parse_page(html, "https://example.com", "", "").unwrap();
+        let texts = chunks.iter().map(|c| c.text.trim()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                "Voici des repères.",
+                "Âge : 1 mois ; Boires par jour : 6 à 8",
+                "Calendrier :",
+                "2 mois | Rotavirus",
+            ]
+        );
 
-        while let Some(res) = set.join_next().await {
-            let page_chunks = res.unwrap().unwrap();
-            let filtered = page_chunks
-                .into_iter()
-                .filter(|chunk| chunk.text.len() > TEXT_MIN_LENGTH);
-            chunks.extend(filtered);
-        }
+        let tables = chunks
+            .iter()
+            .map(|c| c.metadata.table.as_ref().map(|t| t.headers.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(tables, vec![None, Some(2), None, Some(0)]);
+        assert!(chunks
+            .iter()
+            .all(|c| c.metadata.heading_path() == "Alimentation > Quantités"));
+    }
+
+    #[test]
+    fn test_parse_block_types() {
+        let html = r#"<h1>Le bain</h1>
+            <div class="two-column-layout__left"><div class="field__item">
+                <p>Le bain peut être donné chaque jour. <img src="/images/bain.jpg" alt="Un parent lave son bébé"></p>
+                <figure><img src="/images/eau.jpg" alt=""><figcaption>Vérifiez la température de l'eau.</figcaption></figure>
+                <div class="encadre"><p><strong>Important</strong></p><p>Ne laissez jamais votre bébé seul dans le bain.</p></div>
+                <div class="encadre encadre--a-retenir"><h3>À retenir</h3><ul><li>Eau à 37 °C</li><li>Toujours surveiller</li></ul></div>
+                <div class="encadre"><p>Un bain court suffit.</p></div>
+                <div class="encadre"><p>Températures</p><table><tr><th>Eau</th><td>37 °C</td></tr></table></div>
+            </div></div>"#;
+
+        let chunks = parse_page(html, "https://example.com/bain", "", "").unwrap();
+        let blocks = chunks
+            .iter()
+            .map(|c| (c.metadata.block_type, c.text.trim()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                (BlockType::Text, "Le bain peut être donné chaque jour."),
+                (BlockType::Figure, "Image : Un parent lave son bébé"),
+                (
+                    BlockType::Figure,
+                    "Légende : Vérifiez la température de l'eau."
+                ),
+                (
+                    BlockType::Warning,
+                    "Important\nNe laissez jamais votre bébé seul dans le bain."
+                ),
+                (
+                    BlockType::Summary,
+                    "À retenir\n- Eau à 37 °C\n- Toujours surveiller"
+                ),
+                (BlockType::Callout, "Un bain court suffit."),
+                (BlockType::Callout, "Températures"),
+                (BlockType::Table, "Eau | 37 °C"),
+            ]
+        );
+
+        let figure = chunks[1].metadata.figure.as_ref().unwrap();
+        assert_eq!(
+            figure.src.as_deref(),
+            Some("https://example.com/images/bain.jpg")
+        );
+        assert!(chunks
+            .iter()
+            .all(|c| c.metadata.heading_path() == "Le bain"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let missing_title = parse_page(
+            r#"<div class="two-column-layout__left"><div class="field__item"></div></div>"#,
+            "https://example.com/a",
+            "",
+            "",
+        );
+        assert!(matches!(
+            missing_title,
+            Err(CrawlError::MissingTitle { url }) if url == "https://example.com/a"
+        ));
+
+        let missing_content = parse_page("<h1>Titre</h1>", "https://example.com/b", "", "");
+        assert!(matches!(
+            missing_content,
+            Err(CrawlError::MissingContent { url }) if url == "https://example.com/b"
+        ));
+
+        let malformed_menu = parse_section_menu("<p>Rien</p>", "https://example.com/c", "");
+        assert!(matches!(
+            malformed_menu,
+            Err(CrawlError::MalformedMenu { url, .. }) if url == "https://example.com/c"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        write_snapshot(&Snapshot::new(dir.path()), [PAGE_HTML, PAGE_HTML]).await;
+
+        let fetcher = MieuxVivreFetcher::from_snapshot(dir.path());
+        let chunks = fetcher.fetch().await.unwrap();
+
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.metadata.section == "Grossesse"));
+
+        // Chunks come in page discovery order, with IDs that only depend on their content.
+        let urls = chunks
+            .iter()
+            .map(|chunk| chunk.metadata.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![PAGE_URLS[0], PAGE_URLS[0], PAGE_URLS[1], PAGE_URLS[1]]
+        );
+        let ids = chunks.iter().map(|c| c.id.clone()).collect::<HashSet<_>>();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(chunks, fetcher.fetch().await.unwrap());
+
+        // The link on the first page goes with the chunk holding it; on the second page it
+        // links to the page itself and is left out.
+        assert!(chunks[0].metadata.links.is_empty());
+        assert_eq!(
+            chunks[1].metadata.links,
+            vec![Link {
+                url: PAGE_URLS[1].to_string(),
+                text: "le suivi de grossesse".to_string(),
+            }]
+        );
+        assert!(chunks[3].metadata.links.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_crawl_pages_listed_twice_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot::new(dir.path());
+        write_snapshot(&snapshot, [PAGE_HTML, PAGE_HTML]).await;
+        snapshot
+            .write(
+                SECTION_URL,
+                &SECTION_HTML.replace(
+                    "</ul></nav>",
+                    r#"<li><a href="/mieux-vivre/grossesse/les-etapes/suivi">Le suivi</a></li></ul></nav>"#,
+                ),
+            )
+            .await
+            .unwrap();
+
+        let result = MieuxVivreFetcher::from_snapshot(dir.path())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(result.chunks.len(), 4);
+        assert_eq!(result.report.added, PAGE_URLS.to_vec());
+        assert!(result
+            .chunks
+            .iter()
+            .all(|c| c.metadata.subsection == "Les étapes avant la grossesse"));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_keeps_chunks_from_good_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        write_snapshot(
+            &Snapshot::new(dir.path()),
+            [
+                PAGE_HTML,
+                "<html><body><p>Nouvelle mise en page</p></body></html>",
+            ],
+        )
+        .await;
+
+        let fetcher = MieuxVivreFetcher::from_snapshot(dir.path());
+        let result = fetcher.crawl().await.unwrap();
+
+        assert_eq!(result.chunks.len(), 2);
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.failures[0].url(), PAGE_URLS[1]);
+    }
+
+    #[tokio::test]
+    async fn test_incremental_crawl() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot::new(dir.path());
+        write_snapshot(&snapshot, [PAGE_HTML, PAGE_HTML]).await;
+
+        let first = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_previous(CrawlState::default(), vec![])
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(first.report.added, PAGE_URLS.to_vec());
+        assert_eq!(first.state.pages.len(), 2);
+
+        // Edit the first page and drop the second one from the menu.
+        snapshot
+            .write(
+                PAGE_URLS[0],
+                &PAGE_HTML.replace("Fatigue", "Fatigue intense"),
+            )
+            .await
+            .unwrap();
+        snapshot
+            .write(
+                SECTION_URL,
+                &SECTION_HTML.replace(
+                    r#"<ul><li><a href="/mieux-vivre/grossesse/les-etapes/suivi">Le suivi de grossesse</a></li></ul>"#,
+                    "",
+                ),
+            )
+            .await
+            .unwrap();
+
+        let second = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_previous(first.state.clone(), first.chunks.clone())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(second.report.changed, vec![PAGE_URLS[0].to_string()]);
+        assert_eq!(second.report.removed, vec![PAGE_URLS[1].to_string()]);
+        assert_eq!(second.chunks.len(), 2);
+        assert!(second.chunks[1].text.ends_with("Fatigue intense"));
+        assert_eq!(second.chunks[0].id, first.chunks[0].id);
+        assert_ne!(second.chunks[1].id, first.chunks[1].id);
+
+        let third = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_previous(second.state.clone(), second.chunks.clone())
+            .crawl()
+            .await
+            .unwrap();
+        assert!(third.report.is_empty());
+        assert_eq!(third.report.unchanged, 1);
+        assert_eq!(third.chunks, second.chunks);
+    }
+
+    #[tokio::test]
+    async fn test_crawl_keeps_pages_of_failed_section_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot::new(dir.path());
+        write_snapshot(&snapshot, [PAGE_HTML, PAGE_HTML]).await;
+        let birth_url = "https://www.inspq.qc.ca/mieux-vivre/accouchement";
+        let labour_url = "https://www.inspq.qc.ca/mieux-vivre/accouchement/le-travail";
+        snapshot
+            .write(
+                super::ROOT,
+                &ROOT_HTML.replace(
+                    "</body>",
+                    r#"<div class="carte-lien-mv"><a href="/mieux-vivre/accouchement"><img src="a.jpg"><span>Accouchement</span></a></div></body>"#,
+                ),
+            )
+            .await
+            .unwrap();
+        snapshot
+            .write(
+                birth_url,
+                r#"<nav id="block-mieuxvivre-post-content-menu"><ul class="menu">
+                    <li><a href="/mieux-vivre/accouchement/le-travail">Le travail</a></li>
+                </ul></nav>"#,
+            )
+            .await
+            .unwrap();
+        snapshot
+            .write(labour_url, &PAGE_HTML.replace("grossesse", "travail"))
+            .await
+            .unwrap();
+
+        let first = MieuxVivreFetcher::from_snapshot(dir.path())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(first.chunks.last().unwrap().metadata.url, labour_url);
+
+        // The pages of the first section are kept before those of the second one.
+        snapshot
+            .write(SECTION_URL, "<html><body></body></html>")
+            .await
+            .unwrap();
+        let second = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_previous(first.state.clone(), first.chunks.clone())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(second.failures.len(), 1);
+        assert_eq!(second.chunks, first.chunks);
+    }
+
+    #[tokio::test]
+    async fn test_incremental_crawl_of_empty_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = r#"<h1>Vide</h1><div class="two-column-layout__left"><div class="field__item"></div></div>"#;
+        write_snapshot(&Snapshot::new(dir.path()), [PAGE_HTML, empty]).await;
+
+        let first = MieuxVivreFetcher::from_snapshot(dir.path())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(first.report.added, PAGE_URLS.to_vec());
+
+        // A page without chunks is still known from the previous crawl.
+        let second = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_previous(first.state, first.chunks)
+            .crawl()
+            .await
+            .unwrap();
+        assert!(second.report.is_empty());
+        assert_eq!(second.report.unchanged, 2);
+    }
+
+    #[tokio::test]
+    async fn test_recrawl_with_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        write_snapshot(&Snapshot::new(dir.path()), [PAGE_HTML, PAGE_HTML]).await;
+
+        let first = MieuxVivreFetcher::from_snapshot(dir.path())
+            .crawl()
+            .await
+            .unwrap();
 
-        Ok(chunks)
+        // Unchanged pages are re-chunked when the chunker settings change.
+        let second = MieuxVivreFetcher::from_snapshot(dir.path())
+            .with_chunker(ChunkerConfig {
+                target_tokens: 16,
+                max_tokens: 16,
+                ..Default::default()
+            })
+            .with_previous(first.state.clone(), first.chunks.clone())
+            .crawl()
+            .await
+            .unwrap();
+        assert_eq!(second.report.changed, PAGE_URLS.to_vec());
+        assert_ne!(second.chunks, first.chunks);
+        assert_ne!(second.state, first.state);
     }
 }
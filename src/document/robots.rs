@@ -0,0 +1,157 @@
+/// The rules of a robots.txt file that apply to one user agent.
+///
+/// Only `User-agent`, `Allow` and `Disallow` are understood. Rules are plain path prefixes,
+/// with `*` wildcards and a trailing `$` anchor, and the longest matching rule wins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsTxt {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl RobotsTxt {
+    /// Parses the rules of `robots_txt` that apply to `user_agent`, falling back to the
+    /// rules for `*` when no group names our agent.
+    pub fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        let product = user_agent
+            .split('/')
+            .next()
+            .unwrap_or(user_agent)
+            .to_lowercase();
+
+        let mut groups: Vec<(Vec<String>, Vec<Rule>)> = vec![];
+        let mut in_agents = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match field.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((vec![], vec![]));
+                        in_agents = true;
+                    }
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_lowercase());
+                    }
+                }
+                field @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // An empty Disallow allows everything.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some((_, rules)) = groups.last_mut() {
+                        rules.push(Rule {
+                            allow: field == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+
+        let matching = |wanted: &dyn Fn(&str) -> bool| {
+            groups
+                .iter()
+                .filter(|(agents, _)| agents.iter().any(|agent| wanted(agent)))
+                .flat_map(|(_, rules)| rules.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let mut rules = matching(&|agent| agent != "*" && product.contains(agent));
+        if rules.is_empty() {
+            rules = matching(&|agent| agent == "*");
+        }
+
+        Self { rules }
+    }
+
+    /// Whether a URL path (with its query string) may be crawled.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            // On equal lengths, Allow wins.
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        let is_last = i == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RobotsTxt;
+
+    const ROBOTS_TXT: &str = "
+# Drupal
+User-agent: *
+Disallow: /admin/
+Disallow: /*.pdf$
+Disallow: /search
+Allow: /search/guide
+
+User-agent: bebe-ai
+User-agent: otherbot
+Disallow: /private
+";
+
+    #[test]
+    fn test_wildcard_group() {
+        let robots = RobotsTxt::parse(ROBOTS_TXT, "SomeBot/1.0");
+        assert!(robots.is_allowed("/mieux-vivre/grossesse"));
+        assert!(!robots.is_allowed("/admin/config"));
+        assert!(!robots.is_allowed("/search?q=bebe"));
+        assert!(robots.is_allowed("/search/guide"));
+        assert!(!robots.is_allowed("/files/guide.pdf"));
+        assert!(robots.is_allowed("/files/guide.pdf.html"));
+    }
+
+    #[test]
+    fn test_specific_group() {
+        let robots = RobotsTxt::parse(ROBOTS_TXT, "bebe-ai/0.1 (+https://example.com)");
+        assert!(robots.is_allowed("/admin/config"));
+        assert!(!robots.is_allowed("/private/page"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let robots = RobotsTxt::parse("", "bebe-ai");
+        assert!(robots.is_allowed("/anything"));
+    }
+}
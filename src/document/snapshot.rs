@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+/// A local mirror of crawled HTML pages, keyed by URL.
+///
+/// Each page is stored at `<dir>/<host>/<path>.html`, so a snapshot can be browsed
+/// and edited by hand, and committed as test fixtures.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the file a given URL is stored at within the snapshot.
+    pub fn path_for(&self, url: &str) -> PathBuf {
+        let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let without_fragment = without_scheme
+            .split_once('#')
+            .map(|(rest, _)| rest)
+            .unwrap_or(without_scheme);
+
+        let (path, query) = match without_fragment.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (without_fragment, None),
+        };
+
+        let mut file = self.dir.clone();
+        for segment in path.split('/').filter(|s| !s.is_empty() && *s != "..") {
+            file.push(segment);
+        }
+
+        if path.ends_with('/') || !path.contains('/') {
+            file.push("index");
+        }
+
+        let mut name = file.file_name().unwrap().to_string_lossy().into_owned();
+        if let Some(query) = query {
+            name.push('_');
+            name.extend(
+                query
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }),
+            );
+        }
+        name.push_str(".html");
+        file.set_file_name(name);
+
+        file
+    }
+
+    pub async fn read(&self, url: &str) -> std::io::Result<String> {
+        tokio::fs::read_to_string(self.path_for(url)).await
+    }
+
+    pub async fn write(&self, url: &str, body: &str) -> std::io::Result<()> {
+        let path = self.path_for(url);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Snapshot;
+
+    #[test]
+    fn test_path_for() {
+        let snapshot = Snapshot::new("snap");
+
+        assert_eq!(
+            snapshot.path_for("https://www.inspq.qc.ca/mieux-vivre/grossesse"),
+            PathBuf::from("snap/www.inspq.qc.ca/mieux-vivre/grossesse.html")
+        );
+        assert_eq!(
+            snapshot.path_for("https://www.inspq.qc.ca/mieux-vivre/grossesse/"),
+            PathBuf::from("snap/www.inspq.qc.ca/mieux-vivre/grossesse/index.html")
+        );
+        assert_eq!(
+            snapshot.path_for("https://www.inspq.qc.ca"),
+            PathBuf::from("snap/www.inspq.qc.ca/index.html")
+        );
+        assert_eq!(
+            snapshot.path_for("https://www.inspq.qc.ca/page?a=1#top"),
+            PathBuf::from("snap/www.inspq.qc.ca/page_a_1.html")
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{chunker::ChunkerConfig, clean::TextCleaner};
+
+/// What we remember about every crawled page between two crawls.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CrawlState {
+    pub pages: BTreeMap<String, PageState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageState {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Hash of the page's content, used to detect changes when the server sends no validators.
+    pub content_hash: String,
+    /// How the page was turned into chunks, see [`pipeline_fingerprint`]. A page chunked
+    /// differently than the current crawl would is re-chunked even if it did not change.
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+impl CrawlState {
+    /// Loads a crawl state, or an empty state if the file does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+}
+
+/// How a page changed since the previous crawl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageStatus {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+/// The pages that changed since the previous crawl.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CrawlReport {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+}
+
+impl CrawlReport {
+    pub fn record(&mut self, url: &str, status: PageStatus) {
+        match status {
+            PageStatus::Added => self.added.push(url.to_string()),
+            PageStatus::Changed => self.changed.push(url.to_string()),
+            PageStatus::Unchanged => self.unchanged += 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A fingerprint of how pages are turned into chunks: the version of the parser, and the
+/// settings of the cleaner and chunker.
+pub fn pipeline_fingerprint(
+    parser_version: u32,
+    cleaner: &TextCleaner,
+    chunker: &ChunkerConfig,
+) -> String {
+    let settings = serde_json::json!({
+        "parser": parser_version,
+        "cleaner": cleaner.rules(),
+        "chunker": chunker,
+    });
+    content_hash(&settings.to_string())
+}
+
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
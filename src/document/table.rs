@@ -0,0 +1,280 @@
+use scraper::{ElementRef, Selector};
+use serde::{Deserialize, Serialize};
+
+use super::chunker::count_tokens;
+
+/// A table parsed out of a page, such as feeding quantities by age or a vaccination calendar.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub caption: Option<String>,
+    /// The header row, empty when the table has none.
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Parses a `table` element. Cells spanning several columns are repeated in each of
+    /// them so values stay aligned with their header. Returns `None` for empty tables.
+    pub fn parse(table: ElementRef) -> Option<Self> {
+        let caption_selector = Selector::parse("caption").unwrap();
+        let row_selector = Selector::parse("tr").unwrap();
+
+        let caption = table
+            .select(&caption_selector)
+            .next()
+            .map(|caption| clean_cell(caption))
+            .filter(|caption| !caption.is_empty());
+
+        // Only rows of this table, not of tables nested in its cells.
+        let mut rows = table
+            .select(&row_selector)
+            .filter(|row| {
+                row.ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .find(|e| e.value().name() == "table")
+                    == Some(table)
+            })
+            .map(|row| {
+                let in_head = row
+                    .parent()
+                    .and_then(ElementRef::wrap)
+                    .is_some_and(|parent| parent.value().name() == "thead");
+                let cells = row
+                    .child_elements()
+                    .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                    .collect::<Vec<_>>();
+                let all_headers =
+                    !cells.is_empty() && cells.iter().all(|cell| cell.value().name() == "th");
+
+                let cells = cells
+                    .into_iter()
+                    .flat_map(|cell| {
+                        let span = cell
+                            .value()
+                            .attr("colspan")
+                            .and_then(|span| span.parse::<usize>().ok())
+                            .unwrap_or(1)
+                            .clamp(1, 100);
+                        std::iter::repeat_n(clean_cell(cell), span)
+                    })
+                    .collect::<Vec<_>>();
+
+                (in_head || all_headers, cells)
+            })
+            .filter(|(_, cells)| cells.iter().any(|cell| !cell.is_empty()))
+            .collect::<Vec<_>>();
+
+        let headers = match rows.first() {
+            Some((true, _)) => rows.remove(0).1,
+            _ => vec![],
+        };
+        let rows = rows.into_iter().map(|(_, cells)| cells).collect::<Vec<_>>();
+
+        if headers.is_empty() && rows.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            caption,
+            headers,
+            rows,
+        })
+    }
+
+    /// Splits the table into tables of consecutive rows whose text fits in `max_tokens`, each
+    /// keeping the caption and headers so it reads on its own. A row too large by itself
+    /// makes a table of its own.
+    pub fn split(&self, max_tokens: usize) -> Vec<Table> {
+        if count_tokens(&self.to_text()) <= max_tokens {
+            return vec![self.clone()];
+        }
+
+        let empty = || Table {
+            caption: self.caption.clone(),
+            headers: self.headers.clone(),
+            rows: vec![],
+        };
+        let mut pieces = vec![];
+        let mut current = empty();
+        for row in &self.rows {
+            current.rows.push(row.clone());
+            if current.rows.len() > 1 && count_tokens(&current.to_text()) > max_tokens {
+                let row = current.rows.pop().unwrap_or_default();
+                pieces.push(std::mem::replace(&mut current, empty()));
+                current.rows.push(row);
+            }
+        }
+        pieces.push(current);
+        pieces
+    }
+
+    /// A plain text rendering that keeps every value next to its header, one row per line:
+    /// "Âge : 1 mois ; Quantité : 90 ml".
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![];
+        if let Some(caption) = &self.caption {
+            lines.push(caption.clone());
+        }
+
+        for row in &self.rows {
+            let line = if self.headers.is_empty() {
+                row.join(" | ")
+            } else {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(i, value)| match self.headers.get(i) {
+                        Some(header) if !header.is_empty() => format!("{} : {}", header, value),
+                        _ => value.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ; ")
+            };
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+
+    /// A Markdown rendering, for answers that should show the table as a table.
+    pub fn to_markdown(&self) -> String {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(self.headers.len()))
+            .max()
+            .unwrap_or(0);
+
+        let line = |cells: &[String]| {
+            let cells = (0..columns)
+                .map(|i| {
+                    cells
+                        .get(i)
+                        .map_or("", |cell| cell.as_str())
+                        .replace('|', "\\|")
+                })
+                .collect::<Vec<_>>();
+            format!("| {} |", cells.join(" | "))
+        };
+
+        let mut lines = vec![];
+        if let Some(caption) = &self.caption {
+            lines.push(format!("**{}**", caption));
+            lines.push(String::new());
+        }
+        lines.push(line(&self.headers));
+        lines.push(format!("|{}", " --- |".repeat(columns)));
+        lines.extend(self.rows.iter().map(|row| line(row)));
+
+        lines.join("\n")
+    }
+}
+
+fn clean_cell(cell: ElementRef) -> String {
+    cell.text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::{Html, Selector};
+
+    use super::Table;
+    use crate::document::chunker::count_tokens;
+
+    fn parse(html: &str) -> Option<Table> {
+        let document = Html::parse_fragment(html);
+        let selector = Selector::parse("table").unwrap();
+        Table::parse(document.select(&selector).next().unwrap())
+    }
+
+    #[test]
+    fn test_parse_with_header() {
+        let table = parse(
+            r#"<table>
+                <caption>Quantité de lait par boire</caption>
+                <thead><tr><th>Âge</th><th>Quantité</th></tr></thead>
+                <tbody>
+                    <tr><td>1 semaine</td><td>60 à 90 ml</td></tr>
+                    <tr><td>1 mois</td><td><strong>90</strong> à 120 ml</td></tr>
+                </tbody>
+            </table>"#,
+        )
+        .unwrap();
+
+        assert_eq!(table.caption.as_deref(), Some("Quantité de lait par boire"));
+        assert_eq!(table.headers, vec!["Âge", "Quantité"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["1 semaine", "60 à 90 ml"],
+                vec!["1 mois", "90 à 120 ml"]
+            ]
+        );
+        assert_eq!(
+            table.to_text(),
+            "Quantité de lait par boire\nÂge : 1 semaine ; Quantité : 60 à 90 ml\nÂge : 1 mois ; Quantité : 90 à 120 ml"
+        );
+        assert_eq!(
+            table.to_markdown(),
+            "**Quantité de lait par boire**\n\n| Âge | Quantité |\n| --- | --- |\n| 1 semaine | 60 à 90 ml |\n| 1 mois | 90 à 120 ml |"
+        );
+    }
+
+    #[test]
+    fn test_parse_without_header() {
+        let table = parse(
+            r#"<table><tr><th>2 mois</th><td>DCaT-HB-VPI-Hib</td></tr>
+                <tr><td colspan="2">Consultez votre CLSC</td></tr></table>"#,
+        )
+        .unwrap();
+
+        assert!(table.headers.is_empty());
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["2 mois", "DCaT-HB-VPI-Hib"],
+                vec!["Consultez votre CLSC", "Consultez votre CLSC"]
+            ]
+        );
+        assert_eq!(
+            table.to_text(),
+            "2 mois | DCaT-HB-VPI-Hib\nConsultez votre CLSC | Consultez votre CLSC"
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let table = Table {
+            caption: Some("Calendrier".to_string()),
+            headers: vec!["Âge".to_string(), "Vaccin".to_string()],
+            rows: (1..=6)
+                .map(|month| vec![format!("{} mois", month), "Rotavirus".to_string()])
+                .collect(),
+        };
+        assert_eq!(table.split(100), vec![table.clone()]);
+
+        let pieces = table.split(20);
+        assert!(pieces.len() > 1);
+        assert!(pieces.iter().all(|piece| piece.headers == table.headers
+            && piece.caption == table.caption
+            && count_tokens(&piece.to_text()) <= 20));
+        assert_eq!(
+            pieces
+                .into_iter()
+                .flat_map(|piece| piece.rows)
+                .collect::<Vec<_>>(),
+            table.rows
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse("<table><tr><td> </td></tr></table>"), None);
+    }
+}
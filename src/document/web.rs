@@ -0,0 +1,836 @@
+//! A fetcher for any site whose pages can be described with CSS selectors.
+//!
+//! A site is described by a TOML (or JSON) file such as:
+//!
+//! ```toml
+//! name = "naitre-et-grandir"
+//! seeds = ["https://naitreetgrandir.com/fr/etape/0_12_mois/"]
+//! link_selectors = ["main a[href]"]
+//! content_selector = "main article"
+//! title_selector = "h1"
+//! heading_tags = ["h2", "h3", "h4"]
+//! allow = ["^https://naitreetgrandir\\.com/fr/"]
+//! deny = ["\\.pdf$", "/recherche"]
+//! max_depth = 2
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reqwest::Url;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use super::{
+    chunker::{Chunker, ChunkerConfig},
+    clean::{CleanReport, TextCleaner},
+    crawler::{CrawlConfig, Crawler, Fetched, PageSource},
+    error::CrawlError,
+    snapshot::Snapshot,
+    state::{content_hash, CrawlReport, CrawlState, PageState, PageStatus},
+    table::Table,
+    Chunk, ChunkMetadata, CrawlResult, DocumentFetcher,
+};
+
+/// How to crawl a site and find the content of its pages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiteConfig {
+    /// A short name for the site, recorded in the metadata of its chunks.
+    pub name: String,
+    /// The pages the crawl starts from.
+    pub seeds: Vec<String>,
+    /// Elements whose `href` leads to more pages to crawl.
+    #[serde(default = "default_link_selectors")]
+    pub link_selectors: Vec<String>,
+    /// The element holding the main content of a page.
+    pub content_selector: String,
+    /// The element holding the title of a page. Pages without one use their `title`.
+    #[serde(default = "default_title_selector")]
+    pub title_selector: String,
+    /// The heading elements, from `h1` to `h6`, that structure the content.
+    #[serde(default = "default_heading_tags")]
+    pub heading_tags: Vec<String>,
+    /// Regular expressions a discovered URL must match one of to be crawled. When empty,
+    /// only URLs on the same hosts as the seeds are crawled.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Regular expressions excluding discovered URLs, even allowed ones.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// How many links away from the seeds to crawl.
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// The maximum number of pages to crawl.
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+}
+
+fn default_link_selectors() -> Vec<String> {
+    vec!["a[href]".to_string()]
+}
+
+fn default_title_selector() -> String {
+    "h1".to_string()
+}
+
+fn default_heading_tags() -> Vec<String> {
+    vec!["h2".to_string(), "h3".to_string(), "h4".to_string()]
+}
+
+fn default_max_depth() -> usize {
+    3
+}
+
+fn default_max_pages() -> usize {
+    500
+}
+
+/// Why a site config could not be loaded.
+#[derive(Debug)]
+pub enum SiteConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        reason: String,
+    },
+    Selector {
+        selector: String,
+        reason: String,
+    },
+    Pattern(regex::Error),
+    HeadingTag(String),
+}
+
+impl fmt::Display for SiteConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SiteConfigError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            SiteConfigError::Parse { path, reason } => {
+                write!(f, "invalid site config {}: {}", path.display(), reason)
+            }
+            SiteConfigError::Selector { selector, reason } => {
+                write!(f, "invalid selector {:?}: {}", selector, reason)
+            }
+            SiteConfigError::Pattern(e) => write!(f, "invalid URL pattern: {}", e),
+            SiteConfigError::HeadingTag(tag) => {
+                write!(f, "heading tags must be h1 to h6, not {:?}", tag)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SiteConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SiteConfigError::Io { source, .. } => Some(source),
+            SiteConfigError::Pattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl SiteConfig {
+    /// Loads a config from a `.json` file, or from TOML for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SiteConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|source| SiteConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        };
+
+        parsed.map_err(|reason| SiteConfigError::Parse {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WebPageMetadata {
+    /// The [`SiteConfig::name`] of the site the page belongs to.
+    pub site: String,
+    pub title: String,
+    /// The innermost heading the chunk is under, if any.
+    pub heading: Option<String>,
+    /// The page title, then every enclosing heading, outermost first.
+    #[serde(default)]
+    pub headings: Vec<String>,
+    /// The level of the innermost heading, or `None` directly under the title.
+    #[serde(default)]
+    pub heading_level: Option<u8>,
+    pub url: String,
+    /// The table this chunk was extracted from, for chunks holding tabular data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<Table>,
+}
+
+impl WebPageMetadata {
+    /// The heading path, as in "Le sommeil > Les siestes".
+    pub fn heading_path(&self) -> String {
+        self.headings.join(" > ")
+    }
+}
+
+impl ChunkMetadata for WebPageMetadata {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn heading_path(&self) -> String {
+        self.heading_path()
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some(&self.title)
+    }
+}
+
+/// A [`SiteConfig`] with its selectors and patterns compiled.
+#[derive(Debug)]
+struct Site {
+    config: SiteConfig,
+    links: Vec<Selector>,
+    content: Selector,
+    title: Selector,
+    headings: Vec<(String, u8)>,
+    allow: Vec<regex::Regex>,
+    deny: Vec<regex::Regex>,
+    hosts: HashSet<String>,
+}
+
+impl Site {
+    fn new(config: SiteConfig) -> Result<Self, SiteConfigError> {
+        let selector = |selector: &str| {
+            Selector::parse(selector).map_err(|e| SiteConfigError::Selector {
+                selector: selector.to_string(),
+                reason: e.to_string(),
+            })
+        };
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| regex::Regex::new(pattern).map_err(SiteConfigError::Pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let headings = config
+            .heading_tags
+            .iter()
+            .map(|tag| {
+                let tag = tag.to_lowercase();
+                match tag
+                    .strip_prefix('h')
+                    .and_then(|level| level.parse::<u8>().ok())
+                {
+                    Some(level @ 1..=6) => Ok((tag, level)),
+                    _ => Err(SiteConfigError::HeadingTag(tag)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            links: config
+                .link_selectors
+                .iter()
+                .map(|s| selector(s))
+                .collect::<Result<_, _>>()?,
+            content: selector(&config.content_selector)?,
+            title: selector(&config.title_selector)?,
+            headings,
+            allow: patterns(&config.allow)?,
+            deny: patterns(&config.deny)?,
+            hosts: config
+                .seeds
+                .iter()
+                .filter_map(|seed| Url::parse(seed).ok()?.host_str().map(String::from))
+                .collect(),
+            config,
+        })
+    }
+
+    fn is_allowed(&self, url: &Url) -> bool {
+        if self
+            .deny
+            .iter()
+            .any(|pattern| pattern.is_match(url.as_str()))
+        {
+            return false;
+        }
+        if self.allow.is_empty() {
+            return url.host_str().is_some_and(|host| self.hosts.contains(host));
+        }
+        self.allow
+            .iter()
+            .any(|pattern| pattern.is_match(url.as_str()))
+    }
+
+    fn heading_level(&self, name: &str) -> Option<u8> {
+        self.headings
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, level)| *level)
+    }
+
+    /// The links of a page worth crawling, absolute and without fragments, in page order.
+    fn links(&self, document: &Html, url: &str) -> Vec<String> {
+        let Ok(base) = Url::parse(url) else {
+            return vec![];
+        };
+
+        self.links
+            .iter()
+            .flat_map(|selector| document.select(selector))
+            .filter_map(|link| base.join(link.value().attr("href")?).ok())
+            .filter(|link| matches!(link.scheme(), "http" | "https"))
+            .map(|mut link| {
+                link.set_fragment(None);
+                link
+            })
+            .filter(|link| self.is_allowed(link))
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Crawls a site described by a [`SiteConfig`], following links from its seeds.
+pub struct WebFetcher {
+    site: Arc<Site>,
+    source: PageSource,
+    config: CrawlConfig,
+    chunker: Arc<Chunker>,
+    cleaner: Arc<TextCleaner>,
+}
+
+#[derive(Debug)]
+struct CrawledPage {
+    url: String,
+    state: PageState,
+    chunks: Vec<Chunk<WebPageMetadata>>,
+    cleaning: CleanReport,
+    links: Vec<String>,
+    /// Why the page has no chunks, when its content could not be found.
+    failure: Option<CrawlError>,
+}
+
+impl WebFetcher {
+    /// Crawls the live site.
+    pub fn new(config: SiteConfig) -> Result<Self, SiteConfigError> {
+        Self::with_source(config, PageSource::Live)
+    }
+
+    /// Crawls the live site and records every page into the snapshot directory.
+    pub fn recording(config: SiteConfig, dir: impl Into<PathBuf>) -> Result<Self, SiteConfigError> {
+        Self::with_source(config, PageSource::Record(Snapshot::new(dir)))
+    }
+
+    /// Crawls a snapshot directory previously written by [`WebFetcher::recording`].
+    pub fn from_snapshot(
+        config: SiteConfig,
+        dir: impl Into<PathBuf>,
+    ) -> Result<Self, SiteConfigError> {
+        Self::with_source(config, PageSource::Replay(Snapshot::new(dir)))
+    }
+
+    pub fn with_source(config: SiteConfig, source: PageSource) -> Result<Self, SiteConfigError> {
+        Ok(Self {
+            site: Arc::new(Site::new(config)?),
+            source,
+            config: CrawlConfig::default(),
+            chunker: Arc::default(),
+            cleaner: Arc::default(),
+        })
+    }
+
+    pub fn site(&self) -> &SiteConfig {
+        &self.site.config
+    }
+
+    /// Sets the retry, rate limiting and robots.txt behaviour of the crawl.
+    pub fn with_config(mut self, config: CrawlConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets how parsed paragraphs are merged and split into chunks.
+    pub fn with_chunker(mut self, config: ChunkerConfig) -> Self {
+        self.chunker = Arc::new(Chunker::new(config));
+        self
+    }
+
+    /// Sets how the text of parsed blocks is cleaned before chunking.
+    pub fn with_cleaner(mut self, cleaner: TextCleaner) -> Self {
+        self.cleaner = Arc::new(cleaner);
+        self
+    }
+
+    /// Crawls the site breadth first from its seeds, up to `max_depth` links away and
+    /// `max_pages` pages.
+    ///
+    /// Pages that fail are reported in [`CrawlResult::failures`] and skipped. Pages without
+    /// content are reported too, but their links are still followed. Chunks come out in the
+    /// order pages were discovered.
+    pub async fn crawl(&self) -> Result<CrawlResult<WebPageMetadata>, CrawlError> {
+        let site = &self.site.config;
+        tracing::info!("Crawling and chunking {}", site.name);
+
+        let crawler = Arc::new(Crawler::new(self.source.clone(), self.config.clone()));
+        let semaphore = Arc::new(Semaphore::new(self.config.max_concurrency));
+
+        let mut seen = HashSet::new();
+        let mut frontier = site
+            .seeds
+            .iter()
+            .filter(|seed| seen.insert(seed.to_string()))
+            .cloned()
+            .collect::<Vec<_>>();
+        frontier.truncate(site.max_pages);
+
+        let mut chunks = vec![];
+        let mut failures = vec![];
+        let mut state = CrawlState::default();
+        let mut report = CrawlReport::default();
+        let mut cleaning = CleanReport::default();
+
+        for depth in 0..=site.max_depth {
+            if frontier.is_empty() {
+                break;
+            }
+            tracing::info!("Crawling {} pages at depth {}", frontier.len(), depth);
+
+            let mut set = tokio::task::JoinSet::new();
+            for (index, url) in std::mem::take(&mut frontier).into_iter().enumerate() {
+                let site = self.site.clone();
+                let crawler = crawler.clone();
+                let chunker = self.chunker.clone();
+                let cleaner = self.cleaner.clone();
+                let semaphore = semaphore.clone();
+
+                set.spawn(async move {
+                    let result =
+                        get_page_content(&site, &crawler, &chunker, &cleaner, &url, semaphore)
+                            .await;
+                    (index, result)
+                });
+            }
+
+            let mut pages = vec![];
+            while let Some(res) = set.join_next().await {
+                pages.push(res.expect("page crawl task panicked"));
+            }
+            // Pages complete in any order, so they are put back in discovery order.
+            pages.sort_by_key(|(index, _)| *index);
+
+            for (_, result) in pages {
+                let page = match result {
+                    Ok(page) => page,
+                    Err(e) => {
+                        tracing::warn!("Skipping page: {}", e);
+                        failures.push(e);
+                        continue;
+                    }
+                };
+
+                if depth < site.max_depth {
+                    for link in page.links {
+                        if seen.len() >= site.max_pages {
+                            break;
+                        }
+                        if seen.insert(link.clone()) {
+                            frontier.push(link);
+                        }
+                    }
+                }
+
+                if let Some(e) = page.failure {
+                    tracing::warn!("Skipping page: {}", e);
+                    failures.push(e);
+                    continue;
+                }
+
+                report.record(&page.url, PageStatus::Added);
+                state.pages.insert(page.url, page.state);
+                cleaning.merge(&page.cleaning);
+                chunks.extend(page.chunks);
+            }
+        }
+
+        report.added.sort();
+
+        Ok(CrawlResult {
+            chunks,
+            failures,
+            state,
+            report,
+            cleaning,
+        })
+    }
+}
+
+async fn get_page_content(
+    site: &Site,
+    crawler: &Crawler,
+    chunker: &Chunker,
+    cleaner: &TextCleaner,
+    url: &str,
+    semaphore: Arc<Semaphore>,
+) -> Result<CrawledPage, CrawlError> {
+    let _permit = semaphore.acquire().await;
+
+    tracing::info!("Fetching {}", url);
+
+    let (body, etag, last_modified) = match crawler.get_if_modified(url, None).await? {
+        Fetched::Page {
+            body,
+            etag,
+            last_modified,
+        } => (body, etag, last_modified),
+        Fetched::NotModified => {
+            return Err(CrawlError::UnexpectedNotModified {
+                url: url.to_string(),
+            })
+        }
+    };
+
+    let document = Html::parse_document(&body);
+    let links = site.links(&document, url);
+
+    let Some(content) = document.select(&site.content).next() else {
+        return Ok(CrawledPage {
+            url: url.to_string(),
+            state: PageState::default(),
+            chunks: vec![],
+            cleaning: CleanReport::default(),
+            links,
+            failure: Some(CrawlError::MissingContent {
+                url: url.to_string(),
+            }),
+        });
+    };
+
+    let title = page_title(site, &document).unwrap_or_else(|| url.to_string());
+    let state = PageState {
+        etag,
+        last_modified,
+        content_hash: content_hash(&(title.clone() + &content.html())),
+        ..Default::default()
+    };
+
+    let blocks = parse_blocks(site, content, &title, url, chunker.config().max_tokens);
+    let (blocks, cleaning) = cleaner.clean(blocks);
+    let mut chunks = chunker.chunk(blocks);
+    Chunk::assign_ids(&mut chunks);
+
+    Ok(CrawledPage {
+        url: url.to_string(),
+        state,
+        chunks,
+        cleaning,
+        links,
+        failure: None,
+    })
+}
+
+fn page_title(site: &Site, document: &Html) -> Option<String> {
+    let fallback = Selector::parse("title").unwrap();
+    let title = |selector: &Selector| {
+        document
+            .select(selector)
+            .next()
+            .map(|title| collapse(&title.text().collect::<String>()))
+            .filter(|title| !title.is_empty())
+    };
+    title(&site.title).or_else(|| title(&fallback))
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Elements that never hold content worth chunking.
+const SKIPPED: [&str; 10] = [
+    "script", "style", "noscript", "nav", "form", "button", "svg", "iframe", "template", "aside",
+];
+
+/// Elements whose children are walked rather than taken as a single block, when they hold
+/// other blocks.
+const CONTAINERS: [&str; 8] = [
+    "div", "section", "article", "main", "header", "footer", "figure", "details",
+];
+
+/// Elements that make a block of their own. Other elements, such as links and emphasis, are
+/// inline: their text is part of the block around them.
+const BLOCKS: [&str; 7] = [
+    "p",
+    "blockquote",
+    "pre",
+    "dl",
+    "address",
+    "figcaption",
+    "hr",
+];
+
+struct BlockParser<'a> {
+    site: &'a Site,
+    title: &'a str,
+    url: &'a str,
+    /// Tables larger than this are split by rows, see [`Table::split`].
+    max_tokens: usize,
+    /// The headings we are currently under, with their level, outermost first.
+    headings: Vec<(u8, String)>,
+    blocks: Vec<Chunk<WebPageMetadata>>,
+}
+
+fn parse_blocks(
+    site: &Site,
+    content: ElementRef,
+    title: &str,
+    url: &str,
+    max_tokens: usize,
+) -> Vec<Chunk<WebPageMetadata>> {
+    let mut parser = BlockParser {
+        site,
+        title,
+        url,
+        max_tokens,
+        headings: vec![],
+        blocks: vec![],
+    };
+    parser.walk(content);
+    parser.blocks
+}
+
+impl BlockParser<'_> {
+    fn metadata(&self, table: Option<Table>) -> WebPageMetadata {
+        WebPageMetadata {
+            site: self.site.config.name.clone(),
+            title: self.title.to_string(),
+            heading: self.headings.last().map(|(_, heading)| heading.clone()),
+            headings: std::iter::once(self.title.to_string())
+                .chain(self.headings.iter().map(|(_, heading)| heading.clone()))
+                .collect(),
+            heading_level: self.headings.last().map(|(level, _)| *level),
+            url: self.url.to_string(),
+            table,
+        }
+    }
+
+    fn push(&mut self, text: String) {
+        if !text.trim().is_empty() {
+            self.blocks.push(Chunk::new(text, self.metadata(None)));
+        }
+    }
+
+    /// Turns the children of an element into blocks, keeping track of headings along the way.
+    /// Text and inline elements next to each other make a single block.
+    fn walk(&mut self, element: ElementRef) {
+        let mut inline = String::new();
+        for child in element.children() {
+            if let Node::Text(text) = child.value() {
+                inline.push_str(text);
+                continue;
+            }
+            let Some(child) = ElementRef::wrap(child) else {
+                continue;
+            };
+            let name = child.value().name();
+
+            if SKIPPED.contains(&name) {
+                continue;
+            }
+            if !self.is_block(name) {
+                inline.extend(child.text());
+                continue;
+            }
+            self.push(collapse(&std::mem::take(&mut inline)));
+
+            if let Some(level) = self.site.heading_level(name) {
+                self.headings.retain(|(parent, _)| *parent < level);
+                self.headings
+                    .push((level, collapse(&child.text().collect::<String>())));
+            } else if name == "table" {
+                for table in Table::parse(child).map_or(vec![], |t| t.split(self.max_tokens)) {
+                    self.blocks
+                        .push(Chunk::new(table.to_text(), self.metadata(Some(table))));
+                }
+            } else if name == "ul" || name == "ol" {
+                let text = child
+                    .child_elements()
+                    .map(|item| format!("- {}", collapse(&item.text().collect::<String>())))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.push(text);
+            } else if CONTAINERS.contains(&name) && self.holds_blocks(child) {
+                self.walk(child);
+            } else {
+                self.push(child.text().collect());
+            }
+        }
+        self.push(collapse(&inline));
+    }
+
+    fn is_block(&self, name: &str) -> bool {
+        self.site.heading_level(name).is_some()
+            || CONTAINERS.contains(&name)
+            || BLOCKS.contains(&name)
+            || matches!(name, "table" | "ul" | "ol")
+    }
+
+    fn holds_blocks(&self, element: ElementRef) -> bool {
+        element.descendants().filter_map(ElementRef::wrap).any(|e| {
+            let name = e.value().name();
+            e != element
+                && (self.site.heading_level(name).is_some()
+                    || CONTAINERS.contains(&name)
+                    || SKIPPED.contains(&name)
+                    || matches!(name, "p" | "table" | "ul" | "ol" | "blockquote"))
+        })
+    }
+}
+
+impl DocumentFetcher<WebPageMetadata> for WebFetcher {
+    async fn fetch(&self) -> Result<Vec<Chunk<WebPageMetadata>>, Box<dyn std::error::Error>> {
+        let result = self.crawl().await?;
+        for failure in &result.failures {
+            tracing::warn!("{}", failure);
+        }
+        Ok(result.chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SiteConfig, SiteConfigError, WebFetcher};
+    use crate::document::snapshot::Snapshot;
+
+    const CONFIG: &str = r#"
+        name = "exemple"
+        seeds = ["https://example.com/guide/"]
+        link_selectors = ["nav a[href]", "main a[href]"]
+        content_selector = "main article"
+        deny = ["/guide/archives"]
+        max_depth = 1
+    "#;
+
+    const INDEX_HTML: &str = r##"<html><head><title>Guide</title></head><body>
+        <nav><a href="sommeil">Sommeil</a><a href="#haut">Haut</a></nav>
+        <main><a href="/guide/archives/vieux">Archives</a><a href="https://ailleurs.com/">Ailleurs</a></main>
+    </body></html>"##;
+
+    const SLEEP_HTML: &str = r#"<html><body>
+        <nav><a href="/guide/sommeil/siestes">Siestes</a></nav>
+        <main><h1>Le sommeil</h1><article>
+            Le bébé dort <strong>beaucoup</strong>, voir
+            <a href="/guide/sommeil/siestes">les siestes</a>.
+            <script>track();</script>
+            <section>
+                <h2>La nuit</h2>
+                <div><p>Couchez-le sur le dos.</p><ul><li>Pas d'oreiller</li><li>Pas de toutou</li></ul></div>
+                <h3>Les boires</h3>
+                <table><tr><th>Âge</th><th>Boires</th></tr><tr><td>1 mois</td><td>2</td></tr></table>
+            </section>
+        </article></main>
+    </body></html>"#;
+
+    #[test]
+    fn test_load_config() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let toml_path = dir.path().join("site.toml");
+        std::fs::write(&toml_path, CONFIG).unwrap();
+        let config = SiteConfig::load(&toml_path).unwrap();
+        assert_eq!(config.title_selector, "h1");
+        assert_eq!(config.heading_tags, vec!["h2", "h3", "h4"]);
+        assert_eq!(config.max_depth, 1);
+
+        let json_path = dir.path().join("site.json");
+        std::fs::write(&json_path, serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(SiteConfig::load(&json_path).unwrap(), config);
+
+        let invalid = SiteConfig {
+            content_selector: "main >".to_string(),
+            ..config.clone()
+        };
+        assert!(matches!(
+            WebFetcher::new(invalid),
+            Err(SiteConfigError::Selector { .. })
+        ));
+        let invalid = SiteConfig {
+            heading_tags: vec!["strong".to_string()],
+            ..config
+        };
+        assert!(matches!(
+            WebFetcher::new(invalid),
+            Err(SiteConfigError::HeadingTag(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_crawl_site_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = Snapshot::new(dir.path());
+        snapshot
+            .write("https://example.com/guide/", INDEX_HTML)
+            .await
+            .unwrap();
+        snapshot
+            .write("https://example.com/guide/sommeil", SLEEP_HTML)
+            .await
+            .unwrap();
+
+        let config: SiteConfig = toml::from_str(CONFIG).unwrap();
+        let result = WebFetcher::from_snapshot(config, dir.path())
+            .unwrap()
+            .crawl()
+            .await
+            .unwrap();
+
+        // The index has no content, and links beyond max_depth are not followed.
+        let failures = result.failures.iter().map(|e| e.url()).collect::<Vec<_>>();
+        assert_eq!(failures, vec!["https://example.com/guide/"]);
+        assert_eq!(
+            result.report.added,
+            vec!["https://example.com/guide/sommeil"]
+        );
+
+        let chunks = result
+            .chunks
+            .iter()
+            .map(|c| (c.metadata.heading_path(), c.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            vec![
+                (
+                    "Le sommeil".to_string(),
+                    "Le bébé dort beaucoup, voir les siestes."
+                ),
+                (
+                    "Le sommeil > La nuit".to_string(),
+                    "Couchez-le sur le dos.\n- Pas d'oreiller\n- Pas de toutou"
+                ),
+                (
+                    "Le sommeil > La nuit > Les boires".to_string(),
+                    "Âge : 1 mois ; Boires : 2"
+                ),
+            ]
+        );
+        assert!(result.chunks[2].metadata.table.is_some());
+        assert!(result.chunks.iter().all(|c| c.metadata.site == "exemple"));
+    }
+}
@@ -1,129 +1,249 @@
+use futures::{stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
-use crate::document::Chunk;
+use crate::document::{Chunk, ChunkMetadata};
 
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod gemini;
+pub mod hashing;
+pub mod index;
+pub mod ollama;
+pub mod openai;
 pub mod similarity;
 
+use error::EmbeddingError;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddedChunk<M> {
     pub embedding: Vec<f32>,
     pub chunk: Chunk<M>,
 }
 
-pub async fn get_embedded_chunks<M>(chunks: Vec<Chunk<M>>) -> Vec<EmbeddedChunk<M>> {
-    let client = reqwest::Client::new();
-    let gemini_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// Turns text into vectors, with one backend or another.
+pub trait Embedder {
+    /// The model the vectors come from. Vectors from different models cannot be compared.
+    fn model_id(&self) -> &str;
+
+    /// The length of the vectors.
+    fn dimension(&self) -> usize;
+
+    /// Embeds a search query, to compare with the vectors of documents.
+    #[allow(async_fn_in_trait)]
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError>;
+
+    /// Embeds the documents to search in as few requests as the backend allows, returning one
+    /// vector per document, in order.
+    #[allow(async_fn_in_trait)]
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError>;
+}
 
-    let embeddings = generate_batch_embeddings(&client, &chunks, &gemini_key)
-        .await
-        .unwrap();
+/// A text to search, with the title of the page or file it comes from. Backends that embed
+/// queries and documents differently can use the title to place the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Document<'a> {
+    pub text: &'a str,
+    pub title: Option<&'a str>,
+}
 
-    chunks
-        .into_iter()
-        .zip(embeddings)
-        .map(|(chunk, embedding)| EmbeddedChunk {
-            embedding: embedding.values,
-            chunk: chunk,
-        })
-        .collect()
+impl<'a> Document<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text, title: None }
+    }
 }
 
-pub async fn generate_embedding(
-    client: &reqwest::Client,
-    text: &str,
-    gemini_key: &str,
-) -> Result<Vec<f32>, reqwest::Error> {
-    let url = format!("https://generativelanguage.googleapis.com/v1beta/models/text-embedding-004:embedContent?key={}", gemini_key);
-    let payload = json!(
-        {
-            "model": "models/text-embedding-004",
-            "content": {
-                "parts": [
-                    {
-                        "text": text
-                    }
-                ]
-            }
-        }
-    );
-    let response = client.post(url).json(&payload).send().await?;
-    let response: GeminiEmbeddingResponse = response.json().await?;
-    Ok(response.embedding.values)
+/// Any of the embedders, picked at runtime from an [`config::EmbedderConfig`].
+#[derive(Debug, Clone)]
+pub enum AnyEmbedder {
+    Gemini(gemini::GeminiEmbedder),
+    OpenAi(openai::OpenAiEmbedder),
+    Ollama(ollama::OllamaEmbedder),
+    Hashing(hashing::HashingEmbedder),
 }
 
-async fn generate_batch_embeddings<M>(
-    client: &reqwest::Client,
-    chunks: &Vec<Chunk<M>>,
-    gemini_key: &str,
-) -> Result<Vec<GeminiEmbedding>, reqwest::Error> {
-    let batches: Vec<&[Chunk<M>]> = chunks.chunks(100).collect::<Vec<_>>();
-
-    let mut embeddings = vec![];
-
-    for (id, batch) in batches.iter().enumerate() {
-        tracing::info!(
-            "Generating embeddings for batch {} of {}",
-            id + 1,
-            batches.len()
-        );
-
-        let url = format!("https://generativelanguage.googleapis.com/v1beta/models/text-embedding-004:batchEmbedContents?key={}", gemini_key);
-
-        let payload = GeminiBatchEmbeddingRequest {
-            requests: batch
-                .iter()
-                .map(|chunk| BatchEmbeddingRequest {
-                    model: "models/text-embedding-004".to_string(),
-                    content: EmbeddingContent {
-                        parts: vec![EmbeddingPart {
-                            text: chunk.text.clone(),
-                        }],
-                    },
-                })
-                .collect(),
-        };
+impl Embedder for AnyEmbedder {
+    fn model_id(&self) -> &str {
+        match self {
+            AnyEmbedder::Gemini(embedder) => embedder.model_id(),
+            AnyEmbedder::OpenAi(embedder) => embedder.model_id(),
+            AnyEmbedder::Ollama(embedder) => embedder.model_id(),
+            AnyEmbedder::Hashing(embedder) => embedder.model_id(),
+        }
+    }
+
+    fn dimension(&self) -> usize {
+        match self {
+            AnyEmbedder::Gemini(embedder) => embedder.dimension(),
+            AnyEmbedder::OpenAi(embedder) => embedder.dimension(),
+            AnyEmbedder::Ollama(embedder) => embedder.dimension(),
+            AnyEmbedder::Hashing(embedder) => embedder.dimension(),
+        }
+    }
 
-        let response = client.post(url).json(&payload).send().await?;
-        let response: GeminiBatchEmbeddingResponse = response.json().await?;
-        embeddings.extend(response.embeddings);
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        match self {
+            AnyEmbedder::Gemini(embedder) => embedder.embed_query(text).await,
+            AnyEmbedder::OpenAi(embedder) => embedder.embed_query(text).await,
+            AnyEmbedder::Ollama(embedder) => embedder.embed_query(text).await,
+            AnyEmbedder::Hashing(embedder) => embedder.embed_query(text).await,
+        }
     }
 
-    Ok(embeddings)
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        match self {
+            AnyEmbedder::Gemini(embedder) => embedder.embed_documents(documents).await,
+            AnyEmbedder::OpenAi(embedder) => embedder.embed_documents(documents).await,
+            AnyEmbedder::Ollama(embedder) => embedder.embed_documents(documents).await,
+            AnyEmbedder::Hashing(embedder) => embedder.embed_documents(documents).await,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct GeminiBatchEmbeddingResponse {
-    embeddings: Vec<GeminiEmbedding>,
+/// How to split a corpus into batches when embedding it.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// How many chunks to embed per call to the embedder.
+    pub batch_size: usize,
+    /// How many batches to embed at once.
+    pub concurrency: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct GeminiBatchEmbeddingRequest {
-    requests: Vec<BatchEmbeddingRequest>,
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            concurrency: 4,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct BatchEmbeddingRequest {
-    model: String,
-    content: EmbeddingContent,
+/// The model ID of a backend prepending prefixes to queries and documents: the model, then a
+/// short hash of the prefixes, as in "e5-large#1f2e3d4c", since other prefixes give other
+/// vectors. Models used without prefixes keep their name as their ID.
+fn prefixed_model_id(model: &str, query_prefix: &str, document_prefix: &str) -> String {
+    if query_prefix.is_empty() && document_prefix.is_empty() {
+        return model.to_string();
+    }
+    let prefixes = serde_json::json!([query_prefix, document_prefix]).to_string();
+    let hash = crate::document::state::content_hash(&prefixes);
+    format!("{}#{}", model, &hash[..8])
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingContent {
-    parts: Vec<EmbeddingPart>,
+/// Fails unless the server answered with one vector per text.
+fn check_count(expected: usize, vectors: Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+    if vectors.len() != expected {
+        return Err(EmbeddingError::Count {
+            expected,
+            got: vectors.len(),
+        });
+    }
+    Ok(vectors)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EmbeddingPart {
-    text: String,
+/// Embeds chunks as documents, titled with the page or file they come from.
+pub async fn get_embedded_chunks<M: ChunkMetadata>(
+    embedder: &impl Embedder,
+    chunks: Vec<Chunk<M>>,
+) -> Result<Vec<EmbeddedChunk<M>>, EmbeddingError> {
+    embed_chunks(embedder, chunks, &BatchOptions::default(), |_, _| {}).await
 }
 
-#[derive(Debug, Deserialize)]
-struct GeminiEmbeddingResponse {
-    embedding: GeminiEmbedding,
+/// Embeds chunks in batches, several at a time, calling `on_batch` with the number of batches
+/// done and the total after each one. With a [`cache::CachedEmbedder`] saved from `on_batch`,
+/// a run that fails or is interrupted resumes where it stopped.
+pub async fn embed_chunks<M: ChunkMetadata>(
+    embedder: &impl Embedder,
+    chunks: Vec<Chunk<M>>,
+    options: &BatchOptions,
+    mut on_batch: impl FnMut(usize, usize),
+) -> Result<Vec<EmbeddedChunk<M>>, EmbeddingError> {
+    let documents = chunks
+        .iter()
+        .map(|chunk| Document {
+            text: &chunk.text,
+            title: chunk.metadata.title(),
+        })
+        .collect::<Vec<_>>();
+    let batches = documents
+        .chunks(options.batch_size.max(1))
+        .collect::<Vec<_>>();
+
+    // Batches run concurrently but come back in order.
+    let mut results = stream::iter(&batches)
+        .map(|batch| async move {
+            let vectors = embedder.embed_documents(batch).await?;
+            check_count(batch.len(), vectors)
+        })
+        .buffered(options.concurrency.max(1));
+    let mut embeddings = Vec::with_capacity(documents.len());
+    let mut done = 0;
+    while let Some(vectors) = results.next().await {
+        embeddings.extend(vectors?);
+        done += 1;
+        on_batch(done, batches.len());
+    }
+    drop(results);
+
+    Ok(chunks
+        .into_iter()
+        .zip(embeddings)
+        .map(|(chunk, embedding)| EmbeddedChunk { embedding, chunk })
+        .collect())
 }
 
-#[derive(Debug, Deserialize)]
-struct GeminiEmbedding {
-    values: Vec<f32>,
+#[cfg(test)]
+mod tests {
+    use super::{cache::CachedEmbedder, embed_chunks, BatchOptions};
+    use crate::{
+        document::{corpus::Corpus, local::LocalFetcher},
+        test_util::TestEmbedder,
+    };
+
+    #[tokio::test]
+    async fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = (1..=5)
+            .map(|i| format!("## Section {}\n\nTexte numéro {}.\n", i, i))
+            .collect::<String>();
+        std::fs::write(dir.path().join("guide.md"), format!("# Guide\n\n{}", text)).unwrap();
+        let chunks = || {
+            let mut corpus = Corpus::default();
+            corpus.replace(
+                |_| true,
+                LocalFetcher::new(dir.path()).read().unwrap().chunks,
+            );
+            corpus.into_chunks()
+        };
+        let options = BatchOptions {
+            batch_size: 2,
+            concurrency: 1,
+        };
+        let path = dir.path().join("cache.json");
+
+        // Two of the three batches go through before the embedder fails.
+        let embedder = CachedEmbedder::load(TestEmbedder::new().failing_after(2), &path).unwrap();
+        let mut progress = vec![];
+        let result = embed_chunks(&embedder, chunks(), &options, |done, total| {
+            progress.push((done, total));
+            embedder.save().unwrap();
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(progress, vec![(1, 3), (2, 3)]);
+
+        // The next run only embeds the last batch.
+        let embedder = CachedEmbedder::load(TestEmbedder::new().failing_after(1), &path).unwrap();
+        let embedded = embed_chunks(&embedder, chunks(), &options, |_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(embedded.len(), 5);
+        assert_eq!(embedder.stats().hits, 4);
+    }
 }
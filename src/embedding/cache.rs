@@ -0,0 +1,288 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+use super::{check_count, error::EmbeddingError, Document, Embedder};
+use crate::document::state::content_hash;
+
+/// Wraps an embedder so every text is only embedded once per model, keeping the vectors in a
+/// JSON file between runs.
+#[derive(Debug, Clone)]
+pub struct CachedEmbedder<E> {
+    inner: E,
+    path: PathBuf,
+    cache: Arc<Mutex<Cache>>,
+    /// The most vectors to keep, see [`CachedEmbedder::with_limit`].
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    vectors: BTreeMap<String, Vec<f32>>,
+    changed: bool,
+    stats: CacheStats,
+}
+
+/// How many texts were found in the cache, and how many had to be embedded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl<E: Embedder> CachedEmbedder<E> {
+    /// Loads the cache saved at `path`, or starts an empty one if there is none.
+    pub fn load(inner: E, path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let vectors = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            inner,
+            path,
+            cache: Arc::new(Mutex::new(Cache {
+                vectors,
+                ..Default::default()
+            })),
+            limit: None,
+        })
+    }
+
+    /// Keeps at most `limit` vectors, dropping others as new ones come in. As keys are
+    /// hashes, the vectors dropped are in effect picked at random.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Writes the cache back to its file, if anything was added since it was loaded or saved.
+    /// The cache is only locked while serialized, not while written, but this still blocks:
+    /// from async code, call it with `spawn_blocking`.
+    ///
+    /// The file is written aside and then renamed over the previous one, so a save that is
+    /// interrupted leaves the previous cache intact.
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = {
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.changed {
+                return Ok(());
+            }
+            cache.changed = false;
+            serde_json::to_string(&cache.vectors)?
+        };
+        let write = || {
+            let temporary = self.path.with_extension("tmp");
+            let mut file = File::create(&temporary)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, &self.path)
+        };
+        write().inspect_err(|_| {
+            self.cache.lock().unwrap().changed = true;
+        })
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// The key of a query, or of a document with its title: a hash of the model, and of the
+    /// text with its Unicode normalized and its whitespace collapsed.
+    fn key(&self, document: Option<Document>, text: &str) -> String {
+        let normalize = |text: &str| {
+            let text = text.nfc().collect::<String>();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+        let task = match document {
+            None => "query".to_string(),
+            Some(Document { title: None, .. }) => "document".to_string(),
+            Some(Document {
+                title: Some(title), ..
+            }) => format!("document\n{}", normalize(title)),
+        };
+        content_hash(&format!(
+            "{}\n{}\n{}",
+            self.inner.model_id(),
+            task,
+            normalize(text)
+        ))
+    }
+
+    fn get(&self, key: &str) -> Option<Vec<f32>> {
+        let mut cache = self.cache.lock().unwrap();
+        let vector = cache.vectors.get(key).cloned();
+        match vector {
+            Some(_) => cache.stats.hits += 1,
+            None => cache.stats.misses += 1,
+        }
+        vector
+    }
+
+    fn insert(&self, key: String, vector: Vec<f32>) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.vectors.insert(key.clone(), vector);
+        if let Some(limit) = self.limit {
+            while cache.vectors.len() > limit {
+                // Never the vector just added, which may be the first by key.
+                let evicted = match cache.vectors.first_key_value() {
+                    Some((first, _)) if *first == key => cache.vectors.keys().nth(1).cloned(),
+                    first => first.map(|(first, _)| first.clone()),
+                };
+                match evicted {
+                    Some(evicted) => cache.vectors.remove(&evicted),
+                    None => break,
+                };
+            }
+        }
+        cache.changed = true;
+    }
+}
+
+impl<E: Embedder> Embedder for CachedEmbedder<E> {
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let key = self.key(None, text);
+        if let Some(vector) = self.get(&key) {
+            return Ok(vector);
+        }
+
+        let vector = self.inner.embed_query(text).await?;
+        self.insert(key, vector.clone());
+        Ok(vector)
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let keys = documents
+            .iter()
+            .map(|document| self.key(Some(*document), document.text))
+            .collect::<Vec<_>>();
+        let mut vectors = keys.iter().map(|key| self.get(key)).collect::<Vec<_>>();
+
+        // Documents that are missing, each embedded once even if it appears several times.
+        let mut missing: BTreeMap<&str, (Document, Vec<usize>)> = BTreeMap::new();
+        for (index, (key, document)) in keys.iter().zip(documents).enumerate() {
+            if vectors[index].is_none() {
+                missing
+                    .entry(key)
+                    .or_insert((*document, vec![]))
+                    .1
+                    .push(index);
+            }
+        }
+        tracing::info!(
+            "{} of {} documents are already embedded",
+            vectors.iter().filter(|vector| vector.is_some()).count(),
+            documents.len()
+        );
+
+        let missing_documents = missing
+            .values()
+            .map(|(document, _)| *document)
+            .collect::<Vec<_>>();
+        let embedded = check_count(
+            missing_documents.len(),
+            self.inner.embed_documents(&missing_documents).await?,
+        )?;
+        for ((key, (_, indices)), vector) in missing.into_iter().zip(embedded) {
+            for index in indices {
+                vectors[index] = Some(vector.clone());
+            }
+            self.insert(key.to_string(), vector);
+        }
+
+        check_count(documents.len(), vectors.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheStats, CachedEmbedder};
+    use crate::{
+        embedding::{error::EmbeddingError, Document, Embedder},
+        test_util::TestEmbedder,
+    };
+
+    #[tokio::test]
+    async fn test_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+
+        let embedder = CachedEmbedder::load(TestEmbedder::new(), &path).unwrap();
+        let documents = ["Le bain", "Le sommeil", "Le  bain"].map(Document::new);
+        let vectors = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(vectors[0], vectors[2]);
+        assert_eq!(embedder.inner.texts(), 2);
+        embedder.embed_query("Les boires").await.unwrap();
+        embedder.save().unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let embedder = CachedEmbedder::load(TestEmbedder::new(), &path).unwrap();
+        let documents = ["Le sommeil"].map(Document::new);
+        assert_eq!(
+            embedder.embed_documents(&documents).await.unwrap(),
+            vec![vectors[1].clone()]
+        );
+        embedder.embed_query("Les boires").await.unwrap();
+        // Queries and titled documents are kept apart from untitled documents.
+        embedder.embed_query("Le bain").await.unwrap();
+        let titled = Document {
+            text: "Le bain",
+            title: Some("Bébé"),
+        };
+        embedder.embed_documents(&[titled]).await.unwrap();
+        assert_eq!(embedder.inner.texts(), 2);
+        assert_eq!(embedder.stats(), CacheStats { hits: 2, misses: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let embedder = CachedEmbedder::load(TestEmbedder::new(), dir.path().join("cache.json"))
+            .unwrap()
+            .with_limit(2);
+        for query in ["Le bain", "Le sommeil", "Les boires"] {
+            embedder.embed_query(query).await.unwrap();
+        }
+        assert_eq!(embedder.cache.lock().unwrap().vectors.len(), 2);
+        // The last query is always kept.
+        embedder.embed_query("Les boires").await.unwrap();
+        assert_eq!(embedder.stats().hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_short_answer() {
+        let dir = tempfile::tempdir().unwrap();
+        let embedder =
+            CachedEmbedder::load(TestEmbedder::new().short(), dir.path().join("cache.json"))
+                .unwrap();
+        let documents = ["Le bain", "Le sommeil"].map(Document::new);
+        assert!(matches!(
+            embedder.embed_documents(&documents).await,
+            Err(EmbeddingError::Count {
+                expected: 2,
+                got: 1
+            })
+        ));
+        // Nothing is cached from an answer that cannot be matched with its documents.
+        assert!(embedder.cache.lock().unwrap().vectors.is_empty());
+    }
+}
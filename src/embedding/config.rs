@@ -0,0 +1,147 @@
+//! Which embedding backend to use, described by a TOML (or JSON) file such as:
+//!
+//! ```toml
+//! backend = "openai"
+//! base_url = "http://embeddings.internal:8000"
+//! model = "intfloat/multilingual-e5-large"
+//! dimension = 1024
+//! batch_size = 32
+//! ```
+//!
+//! The `backend` is one of `gemini`, `openai`, `ollama` or `hashing`, and the other fields are
+//! those of [`GeminiConfig`], [`OpenAiConfig`], [`OllamaConfig`] or [`HashingConfig`]. The
+//! `hashing` backend runs offline, as in:
+//!
+//! ```toml
+//! backend = "hashing"
+//! dimension = 512
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::EmbeddingError,
+    gemini::{GeminiConfig, GeminiEmbedder},
+    hashing::{HashingConfig, HashingEmbedder},
+    ollama::{OllamaConfig, OllamaEmbedder},
+    openai::{OpenAiConfig, OpenAiEmbedder},
+    AnyEmbedder,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum EmbedderConfig {
+    Gemini(GeminiConfig),
+    #[serde(rename = "openai")]
+    OpenAi(OpenAiConfig),
+    Ollama(OllamaConfig),
+    Hashing(HashingConfig),
+}
+
+impl Default for EmbedderConfig {
+    fn default() -> Self {
+        EmbedderConfig::Gemini(GeminiConfig::default())
+    }
+}
+
+impl EmbedderConfig {
+    /// Loads a config from a `.json` file, or from TOML for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EmbeddingError> {
+        let path = path.as_ref();
+        let config_error = |reason: String| EmbeddingError::Config {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let text = std::fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        let config: Self = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| config_error(e.to_string()))?
+        } else {
+            toml::from_str(&text).map_err(|e| config_error(e.to_string()))?
+        };
+        if let EmbedderConfig::Hashing(hashing) = &config {
+            hashing.validate().map_err(config_error)?;
+        }
+        Ok(config)
+    }
+
+    /// The embedder described by the config, reading API keys from the environment.
+    pub fn build(self) -> Result<AnyEmbedder, EmbeddingError> {
+        Ok(match self {
+            EmbedderConfig::Gemini(config) => {
+                AnyEmbedder::Gemini(GeminiEmbedder::from_config(config)?)
+            }
+            EmbedderConfig::OpenAi(config) => AnyEmbedder::OpenAi(OpenAiEmbedder::new(config)?),
+            EmbedderConfig::Ollama(config) => AnyEmbedder::Ollama(OllamaEmbedder::new(config)),
+            EmbedderConfig::Hashing(config) => AnyEmbedder::Hashing(HashingEmbedder::new(config)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmbedderConfig;
+    use crate::embedding::{
+        gemini::GeminiConfig, hashing::HashingConfig, ollama::OllamaConfig, openai::OpenAiConfig,
+        Embedder,
+    };
+
+    #[test]
+    fn test_load_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let load = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            EmbedderConfig::load(&path)
+        };
+
+        assert_eq!(
+            load("gemini.toml", "backend = \"gemini\"").unwrap(),
+            EmbedderConfig::Gemini(GeminiConfig::default())
+        );
+        assert_eq!(
+            load(
+                "openai.json",
+                r#"{"backend": "openai", "base_url": "http://localhost:8000", "model": "e5", "dimension": 1024}"#
+            )
+            .unwrap(),
+            EmbedderConfig::OpenAi(OpenAiConfig {
+                base_url: "http://localhost:8000".to_string(),
+                model: "e5".to_string(),
+                dimension: 1024,
+                batch_size: 64,
+                api_key_env: None,
+                query_prefix: String::new(),
+                document_prefix: String::new(),
+            })
+        );
+        assert_eq!(
+            load(
+                "ollama.toml",
+                "backend = \"ollama\"\nmodel = \"nomic-embed-text\"\ndimension = 768"
+            )
+            .unwrap(),
+            EmbedderConfig::Ollama(OllamaConfig {
+                base_url: "http://localhost:11434".to_string(),
+                model: "nomic-embed-text".to_string(),
+                dimension: 768,
+                batch_size: 8,
+                query_prefix: String::new(),
+                document_prefix: String::new(),
+            })
+        );
+        let hashing = load("hashing.toml", "backend = \"hashing\"\ndimension = 64").unwrap();
+        assert_eq!(
+            hashing,
+            EmbedderConfig::Hashing(HashingConfig {
+                dimension: 64,
+                ..Default::default()
+            })
+        );
+        assert_eq!(hashing.build().unwrap().model_id(), "hashing-64-3-5");
+        assert!(load("bad.toml", "backend = \"word2vec\"").is_err());
+        assert!(load("empty.toml", "backend = \"hashing\"\ndimension = 0").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use std::{fmt, path::PathBuf};
+
+/// Why texts could not be embedded.
+#[derive(Debug)]
+pub enum EmbeddingError {
+    /// The environment variable holding the API key is not set.
+    MissingKey { variable: String },
+    /// The request failed, or the server answered with an error status.
+    Http(reqwest::Error),
+    /// The embedder config could not be read or parsed.
+    Config { path: PathBuf, reason: String },
+    /// The server answered with another number of vectors than texts it was sent.
+    Count { expected: usize, got: usize },
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingError::MissingKey { variable } => {
+                write!(f, "no API key: {} is not set", variable)
+            }
+            EmbeddingError::Http(source) => write!(f, "embedding request failed: {}", source),
+            EmbeddingError::Config { path, reason } => {
+                write!(f, "invalid embedder config {}: {}", path.display(), reason)
+            }
+            EmbeddingError::Count { expected, got } => {
+                write!(f, "got {} vectors for {} texts", got, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmbeddingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmbeddingError::Http(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmbeddingError {
+    fn from(error: reqwest::Error) -> Self {
+        EmbeddingError::Http(error)
+    }
+}
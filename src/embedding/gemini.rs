@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+
+use super::{check_count, error::EmbeddingError, Document, Embedder};
+use crate::http::{send_with_retry, RetryPolicy};
+
+const BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Google's Gemini API. Every field has a default, for `text-embedding-004`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeminiConfig {
+    pub base_url: String,
+    pub model: String,
+    /// The length of the model's vectors.
+    pub dimension: usize,
+    /// The environment variable holding the API key.
+    pub api_key_env: String,
+    /// Truncates the vectors to this many dimensions, for models that support it.
+    pub output_dimensionality: Option<usize>,
+}
+
+impl Default for GeminiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            model: "text-embedding-004".to_string(),
+            dimension: 768,
+            api_key_env: "GEMINI_API_KEY".to_string(),
+            output_dimensionality: None,
+        }
+    }
+}
+
+/// Embeds texts with Google's Gemini API.
+#[derive(Debug, Clone)]
+pub struct GeminiEmbedder {
+    client: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+    dimension: usize,
+    output_dimensionality: Option<usize>,
+    retry: RetryPolicy,
+    /// The model, and the output dimensionality if any, as vectors truncated to different
+    /// lengths cannot be compared.
+    model_id: String,
+}
+
+impl GeminiEmbedder {
+    /// Gemini allows at most 100 texts per batch request.
+    const BATCH_SIZE: usize = 100;
+
+    /// An embedder using `text-embedding-004`, whose vectors have 768 dimensions.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        let config = GeminiConfig::default();
+        Self {
+            client: reqwest::Client::new(),
+            api_key: api_key.into(),
+            base_url: config.base_url,
+            model_id: config.model.clone(),
+            model: config.model,
+            dimension: config.dimension,
+            output_dimensionality: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// An embedder using the API key in `GEMINI_API_KEY`.
+    pub fn from_env() -> Result<Self, EmbeddingError> {
+        Self::from_config(GeminiConfig::default())
+    }
+
+    /// An embedder as configured, reading its API key from the environment.
+    pub fn from_config(config: GeminiConfig) -> Result<Self, EmbeddingError> {
+        let api_key =
+            std::env::var(&config.api_key_env).map_err(|_| EmbeddingError::MissingKey {
+                variable: config.api_key_env.clone(),
+            })?;
+        let embedder = Self::new(api_key)
+            .with_model(config.model, config.dimension)
+            .with_base_url(config.base_url);
+        Ok(match config.output_dimensionality {
+            Some(dimension) => embedder.with_output_dimensionality(dimension),
+            None => embedder,
+        })
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>, dimension: usize) -> Self {
+        self.model = model.into();
+        self.model_id = self.model.clone();
+        self.dimension = dimension;
+        self.output_dimensionality = None;
+        self
+    }
+
+    /// Asks for vectors truncated to `dimension`, for models that support it.
+    pub fn with_output_dimensionality(mut self, dimension: usize) -> Self {
+        self.output_dimensionality = Some(dimension);
+        self.model_id = format!("{}@{}", self.model, dimension);
+        self
+    }
+
+    /// Sends requests to another server than Google's, such as a proxy or a stand-in.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Retries rate-limited and failed requests as `retry` says.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// A request to one of the model's methods. The API key goes in a header rather than
+    /// in the URL, which ends up in logs and error messages.
+    fn post(&self, method: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!(
+                "{}/models/{}:{}",
+                self.base_url, self.model, method
+            ))
+            .header("x-goog-api-key", &self.api_key)
+    }
+
+    fn request(&self, text: &str, task_type: TaskType, title: Option<&str>) -> EmbeddingRequest {
+        EmbeddingRequest {
+            model: format!("models/{}", self.model),
+            content: EmbeddingContent {
+                parts: vec![EmbeddingPart {
+                    text: text.to_string(),
+                }],
+            },
+            task_type,
+            title: title.map(String::from),
+            output_dimensionality: self.output_dimensionality,
+        }
+    }
+}
+
+impl Embedder for GeminiEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn dimension(&self) -> usize {
+        self.output_dimensionality.unwrap_or(self.dimension)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let request =
+            self.post("embedContent")
+                .json(&self.request(text, TaskType::RetrievalQuery, None));
+        let response: GeminiEmbeddingResponse = send_with_retry(request, &self.retry, None)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.embedding.values)
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let mut embeddings = vec![];
+
+        for batch in documents.chunks(Self::BATCH_SIZE) {
+            let payload = GeminiBatchEmbeddingRequest {
+                requests: batch
+                    .iter()
+                    .map(|document| {
+                        self.request(document.text, TaskType::RetrievalDocument, document.title)
+                    })
+                    .collect(),
+            };
+
+            let request = self.post("batchEmbedContents").json(&payload);
+            let response: GeminiBatchEmbeddingResponse =
+                send_with_retry(request, &self.retry, None)
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+            let batch_embeddings = response.embeddings.into_iter().map(|e| e.values).collect();
+            embeddings.extend(check_count(batch.len(), batch_embeddings)?);
+        }
+
+        Ok(embeddings)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiBatchEmbeddingResponse {
+    embeddings: Vec<GeminiEmbedding>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GeminiBatchEmbeddingRequest {
+    requests: Vec<EmbeddingRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmbeddingRequest {
+    model: String,
+    content: EmbeddingContent,
+    task_type: TaskType,
+    /// The title of the document, only allowed with `RETRIEVAL_DOCUMENT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<usize>,
+}
+
+/// What Gemini embeds a text for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum TaskType {
+    RetrievalQuery,
+    RetrievalDocument,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmbeddingContent {
+    parts: Vec<EmbeddingPart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmbeddingPart {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiEmbeddingResponse {
+    embedding: GeminiEmbedding,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiEmbedding {
+    values: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{
+        extract::State,
+        http::{HeaderMap, StatusCode, Uri},
+        response::{IntoResponse, Response},
+        routing::post,
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+
+    use super::GeminiEmbedder;
+    use crate::{
+        embedding::{error::EmbeddingError, Document, Embedder},
+        http::RetryPolicy,
+        test_util::serve,
+    };
+
+    /// Fails unless the API key is sent in its header, and not in the URL.
+    fn check_key(headers: &HeaderMap, uri: &Uri) {
+        assert_eq!(headers["x-goog-api-key"], "key");
+        assert_eq!(uri.query(), None);
+    }
+
+    /// Embeds each document as `[length of its text, length of its title]`.
+    async fn batch(headers: HeaderMap, uri: Uri, Json(request): Json<Value>) -> Json<Value> {
+        check_key(&headers, &uri);
+        let embeddings = request["requests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                assert_eq!(request["taskType"], "RETRIEVAL_DOCUMENT");
+                let text = request["content"]["parts"][0]["text"].as_str().unwrap();
+                let title = request["title"].as_str().unwrap_or_default();
+                json!({ "values": [text.len() as f32, title.len() as f32] })
+            })
+            .collect::<Vec<_>>();
+        Json(json!({ "embeddings": embeddings }))
+    }
+
+    async fn query(headers: HeaderMap, uri: Uri, Json(request): Json<Value>) -> Json<Value> {
+        check_key(&headers, &uri);
+        assert_eq!(request["taskType"], "RETRIEVAL_QUERY");
+        assert_eq!(request["title"], Value::Null);
+        let dimension = request["outputDimensionality"].as_u64().unwrap_or(2);
+        Json(json!({ "embedding": { "values": vec![0.5; dimension as usize] } }))
+    }
+
+    #[tokio::test]
+    async fn test_embed_batch() {
+        let app = Router::new()
+            .route("/v1beta/models/test-model:batchEmbedContents", post(batch))
+            .route("/v1beta/models/test-model:embedContent", post(query));
+        let base_url = format!("{}/v1beta", serve(app).await);
+
+        let embedder = GeminiEmbedder::new("key")
+            .with_model("test-model", 2)
+            .with_base_url(base_url);
+        assert_eq!(embedder.model_id(), "test-model");
+
+        let texts = (0..150).map(|i| "a".repeat(i)).collect::<Vec<_>>();
+        let documents = texts
+            .iter()
+            .map(|text| Document {
+                text,
+                title: Some("Le bain"),
+            })
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(embeddings.len(), 150);
+        assert_eq!(embeddings[120], vec![120.0, 7.0]);
+
+        assert_eq!(
+            embedder.embed_query("bonjour").await.unwrap(),
+            vec![0.5, 0.5]
+        );
+
+        let embedder = embedder.with_output_dimensionality(3);
+        assert_eq!(embedder.model_id(), "test-model@3");
+        assert_eq!(embedder.dimension(), 3);
+        assert_eq!(embedder.embed_query("bonjour").await.unwrap().len(), 3);
+    }
+
+    /// Rate limits the first request, then drops a vector from every answer.
+    async fn flaky(State(calls): State<Arc<AtomicUsize>>, Json(request): Json<Value>) -> Response {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "0")]).into_response();
+        }
+        let count = request["requests"].as_array().unwrap().len();
+        let embeddings = vec![json!({ "values": [1.0] }); count - 1];
+        Json(json!({ "embeddings": embeddings })).into_response()
+    }
+
+    #[tokio::test]
+    async fn test_retry_and_count() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/models/test-model:batchEmbedContents", post(flaky))
+            .with_state(calls.clone());
+        let base_url = serve(app).await;
+
+        let embedder = GeminiEmbedder::new("key")
+            .with_model("test-model", 1)
+            .with_base_url(base_url)
+            .with_retry(RetryPolicy {
+                max_retries: 1,
+                ..Default::default()
+            });
+        let documents = ["a", "b"].map(Document::new);
+        let error = embedder.embed_documents(&documents).await.unwrap_err();
+        assert!(matches!(
+            error,
+            EmbeddingError::Count {
+                expected: 2,
+                got: 1
+            }
+        ));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{error::EmbeddingError, Document, Embedder};
+
+/// The [`HashingEmbedder`]'s settings. Every field has a default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashingConfig {
+    /// The length of the vectors.
+    pub dimension: usize,
+    /// The shortest character n-gram, counting the spaces around words.
+    pub min_ngram: usize,
+    /// The longest character n-gram.
+    pub max_ngram: usize,
+}
+
+impl Default for HashingConfig {
+    fn default() -> Self {
+        Self {
+            dimension: 512,
+            min_ngram: 3,
+            max_ngram: 5,
+        }
+    }
+}
+
+impl HashingConfig {
+    /// Why the config cannot make vectors, if it cannot.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.dimension == 0 {
+            return Err("the dimension must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// An embedder that runs offline: every word and character n-gram of the text is hashed into
+/// one of `dimension` buckets. The vectors are deterministic, but only capture shared
+/// spelling, not meaning.
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    config: HashingConfig,
+    model_id: String,
+}
+
+impl HashingEmbedder {
+    /// Panics if the config is not valid, see [`HashingConfig::validate`].
+    pub fn new(config: HashingConfig) -> Self {
+        if let Err(reason) = config.validate() {
+            panic!("invalid hashing config: {}", reason);
+        }
+        let model_id = format!(
+            "hashing-{}-{}-{}",
+            config.dimension, config.min_ngram, config.max_ngram
+        );
+        Self { config, model_id }
+    }
+
+    /// Embeds a text right away, as this embedder never waits nor fails.
+    pub fn vector(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.config.dimension];
+        let mut add = |feature: &[char]| {
+            let hash = fnv1a(feature);
+            let index = (hash % vector.len() as u64) as usize;
+            // The sign spreads collisions out instead of letting them pile up.
+            vector[index] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        };
+
+        for word in normalize(text).split_whitespace() {
+            let padded = format!(" {} ", word).chars().collect::<Vec<_>>();
+            add(&padded);
+            for n in self.config.min_ngram..=self.config.max_ngram {
+                if n == 0 || n >= padded.len() {
+                    continue;
+                }
+                padded.windows(n).for_each(&mut add);
+            }
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+/// Lowercases the text, removes accents, and keeps only letters and digits.
+fn normalize(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect()
+}
+
+/// The 64-bit FNV-1a hash, which unlike the standard library's hasher is the same on every
+/// platform and Rust version.
+fn fnv1a(chars: &[char]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    let mut buffer = [0; 4];
+    for c in chars {
+        for byte in c.encode_utf8(&mut buffer).bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+impl Embedder for HashingEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn dimension(&self) -> usize {
+        self.config.dimension
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        Ok(self.vector(text))
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        Ok(documents
+            .iter()
+            .map(|document| self.vector(document.text))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashingConfig, HashingEmbedder};
+    use crate::{
+        document::{corpus::Corpus, local::LocalFetcher},
+        embedding::{
+            get_embedded_chunks,
+            similarity::{naive::NaiveSimilarity, SimilarityFinder},
+            Embedder,
+        },
+    };
+
+    #[test]
+    fn test_vectors() {
+        let embedder = HashingEmbedder::new(HashingConfig::default());
+        let vector = embedder.vector("Le bébé dort.");
+        assert_eq!(vector.len(), 512);
+        assert_eq!(vector, embedder.vector("le  BEBE dort"));
+        assert!((vector.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(embedder.vector("").iter().all(|x| *x == 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_end_to_end() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("guide.md"),
+            "# Guide\n\n## Sommeil\n\nCouchez toujours votre bébé sur le dos pour dormir.\n\n\
+             ## Allaitement\n\nLe lait maternel protège votre enfant contre les infections.\n\n\
+             ## Bain\n\nVérifiez la température de l'eau du bain avec votre coude.\n",
+        )
+        .unwrap();
+        let mut corpus = Corpus::default();
+        corpus.replace(
+            |_| true,
+            LocalFetcher::new(dir.path()).read().unwrap().chunks,
+        );
+
+        let embedder = HashingEmbedder::new(HashingConfig::default());
+        let embedded = get_embedded_chunks(&embedder, corpus.into_chunks())
+            .await
+            .unwrap();
+        assert_eq!(embedded.len(), 3);
+
+        let query = embedder
+            .embed_query("Comment coucher bébé pour dormir ?")
+            .await
+            .unwrap();
+        let found = NaiveSimilarity {}.find_k_similar(&query, &embedded, 1);
+        assert_eq!(found[0].chunk.metadata.heading_path(), "Guide > Sommeil");
+
+        let query = embedder.embed_query("température du bain").await.unwrap();
+        let found = NaiveSimilarity {}.find_k_similar(&query, &embedded, 1);
+        assert_eq!(found[0].chunk.metadata.heading_path(), "Guide > Bain");
+    }
+}
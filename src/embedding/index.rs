@@ -0,0 +1,785 @@
+//! A vector index on disk, read through a memory map rather than parsed into memory.
+//!
+//! The vectors file starts with a header, then holds every vector back to back:
+//!
+//! | Bytes        | Content                                            |
+//! |--------------|----------------------------------------------------|
+//! | 8            | the magic bytes `BEBEIDX\0`                        |
+//! | 2            | the format version, 1                              |
+//! | 2            | the precision: 0 for f32, 1 for f16                |
+//! | 4            | the dimension of the vectors                       |
+//! | 8            | the number of vectors                              |
+//! | 32           | the SHA-256 of the vector block                    |
+//! | 2 + n        | the length of the model ID, then the ID in UTF-8   |
+//! | up to 7      | zeros, so the vectors start on a multiple of 8     |
+//!
+//! Numbers are little-endian, and the vectors are read in place as such. The chunks, with
+//! their text and metadata, are kept next to it as JSON, along with an [`IndexManifest`]
+//! describing how the index was made: `index.bin` goes with `index.chunks.json`, in the same
+//! order as the vectors, and `index.manifest.json`.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use half::f16;
+use memmap2::Mmap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    similarity::{
+        cosine_similarity,
+        quantized::{Quantization, QuantizedSimilarity, QuantizedVectors},
+    },
+    EmbeddedChunk, Embedder,
+};
+use crate::{
+    document::{chunker::ChunkerConfig, state::content_hash, Chunk},
+    store::{Store, StoreError},
+};
+
+const MAGIC: &[u8; 8] = b"BEBEIDX\0";
+const VERSION: u16 = 1;
+
+/// How the vectors are stored. Half precision halves the file for a negligible loss in
+/// ranking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    F32,
+    F16,
+}
+
+impl Precision {
+    fn code(self) -> u16 {
+        match self {
+            Precision::F32 => 0,
+            Precision::F16 => 1,
+        }
+    }
+
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            0 => Some(Precision::F32),
+            1 => Some(Precision::F16),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Precision::F32 => 4,
+            Precision::F16 => 2,
+        }
+    }
+}
+
+/// Why an index could not be written or opened.
+#[derive(Debug)]
+pub enum IndexError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The file is not an index, or is truncated or corrupted.
+    Invalid { path: PathBuf, reason: String },
+    /// The index's vectors cannot be compared with those of the embedder or query.
+    Incompatible { reason: String },
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::Io { path, source } => {
+                write!(f, "failed to access {}: {}", path.display(), source)
+            }
+            IndexError::Json { path, source } => {
+                write!(f, "invalid chunks in {}: {}", path.display(), source)
+            }
+            IndexError::Invalid { path, reason } => {
+                write!(f, "invalid index {}: {}", path.display(), reason)
+            }
+            IndexError::Incompatible { reason } => write!(f, "incompatible index: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IndexError::Io { source, .. } => Some(source),
+            IndexError::Json { source, .. } => Some(source),
+            IndexError::Invalid { .. } | IndexError::Incompatible { .. } => None,
+        }
+    }
+}
+
+/// How an index was made, to tell whether it can be searched with an embedder and whether it
+/// is up to date.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexManifest {
+    /// The [`Embedder::model_id`] of the vectors.
+    pub model_id: String,
+    pub dimension: usize,
+    /// When the chunks were last crawled, as `YYYY-MM-DD HH:MM:SS` in UTC, if known.
+    pub crawled_at: Option<String>,
+    /// The [`CrawlRecord::sequence`] of that crawl, missing from older manifests.
+    ///
+    /// [`CrawlRecord::sequence`]: crate::store::CrawlRecord::sequence
+    #[serde(default)]
+    pub crawl_sequence: Option<i64>,
+    /// The chunker settings each source was crawled with, by
+    /// [`Source::key`](crate::document::corpus::Source::key).
+    pub chunker: BTreeMap<String, ChunkerConfig>,
+    /// See [`IndexManifest::source_hash`].
+    pub source_hash: String,
+}
+
+impl IndexManifest {
+    /// A hash of the IDs of the chunks, in order. A chunk's ID changes with its text and its
+    /// place in its source, so the hash changes with any of them.
+    pub fn source_hash<'a, M: 'a>(chunks: impl IntoIterator<Item = &'a Chunk<M>>) -> String {
+        let ids = chunks
+            .into_iter()
+            .map(|chunk| chunk.id.as_str())
+            .collect::<Vec<_>>();
+        content_hash(&ids.join("\n"))
+    }
+
+    /// Fails unless the queries of `embedder` can be compared with the index's vectors: they
+    /// must come from the same model, with the same dimension.
+    pub fn check(&self, embedder: &impl Embedder) -> Result<(), IndexError> {
+        if embedder.model_id() != self.model_id {
+            return Err(IndexError::Incompatible {
+                reason: format!(
+                    "made with {}, but queries are embedded with {}",
+                    self.model_id,
+                    embedder.model_id()
+                ),
+            });
+        }
+        if embedder.dimension() != self.dimension {
+            return Err(IndexError::Incompatible {
+                reason: format!(
+                    "vectors have {} dimensions, but queries have {}",
+                    self.dimension,
+                    embedder.dimension()
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// How the store changed since the index was made from it: a later crawl, sources
+    /// chunked with other settings, or other chunks. Empty when the index is up to date.
+    pub fn changes_since(&self, store: &Store) -> Result<Vec<String>, StoreError> {
+        let mut changes = vec![];
+        if let Some(last_crawl) = store.last_crawl()? {
+            let later = match self.crawl_sequence {
+                Some(sequence) => last_crawl.sequence > sequence,
+                None => Some(&last_crawl.crawled_at) > self.crawled_at.as_ref(),
+            };
+            if later {
+                changes.push(format!(
+                    "a source was crawled again on {}",
+                    last_crawl.crawled_at
+                ));
+            }
+        }
+
+        let crawls = store.crawls()?;
+        let sources = crawls
+            .keys()
+            .chain(self.chunker.keys())
+            .collect::<std::collections::BTreeSet<_>>();
+        for source in sources {
+            match (self.chunker.get(source), crawls.get(source)) {
+                (Some(indexed), Some(crawl)) if *indexed != crawl.chunker => {
+                    changes.push(format!("{} was chunked with other settings", source))
+                }
+                (None, Some(_)) => changes.push(format!("{} was crawled", source)),
+                (Some(_), None) => changes.push(format!("{} is no longer crawled", source)),
+                _ => {}
+            }
+        }
+
+        if Self::source_hash(store.corpus()?.chunks()) != self.source_hash {
+            changes.push("the chunks in the store changed".to_string());
+        }
+        Ok(changes)
+    }
+
+    /// Checks an index before searching it with `embedder`, for the binaries that do. Fails
+    /// if the vectors cannot be compared with the queries, unless `allow_mismatch`, in which
+    /// case it only warns; and warns about anything that changed in the store since.
+    pub fn check_before_search(
+        &self,
+        embedder: &impl Embedder,
+        store: &Store,
+        allow_mismatch: bool,
+    ) -> Result<(), IndexError> {
+        if let Err(e) = self.check(embedder) {
+            if !allow_mismatch {
+                return Err(e);
+            }
+            tracing::warn!("{}; answers will be irrelevant", e);
+        }
+
+        match self.changes_since(store) {
+            Ok(changes) => {
+                for change in changes {
+                    tracing::warn!(
+                        "Since the index was made, {}; run embed to update it",
+                        change
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Could not compare the index with the store: {}", e),
+        }
+        Ok(())
+    }
+}
+
+/// The file holding the chunks of the index at `path`.
+pub fn chunks_path(path: &Path) -> PathBuf {
+    path.with_extension("chunks.json")
+}
+
+/// The file holding the manifest of the index at `path`.
+pub fn manifest_path(path: &Path) -> PathBuf {
+    path.with_extension("manifest.json")
+}
+
+/// Writes embedded chunks as an index at `path`, with their chunks and manifest next to it.
+/// The files are written aside and renamed into place, so a server mapping the old index keeps
+/// reading it.
+pub fn write<M: Serialize>(
+    path: impl AsRef<Path>,
+    manifest: &IndexManifest,
+    embedded: &[EmbeddedChunk<M>],
+    precision: Precision,
+) -> Result<(), IndexError> {
+    let path = path.as_ref();
+    let dimension = manifest.dimension;
+    if let Some(e) = embedded.iter().find(|e| e.embedding.len() != dimension) {
+        return Err(IndexError::Invalid {
+            path: path.to_path_buf(),
+            reason: format!(
+                "chunk {} has {} dimensions instead of {}",
+                e.chunk.id,
+                e.embedding.len(),
+                dimension
+            ),
+        });
+    }
+
+    let mut vectors = Vec::with_capacity(embedded.len() * dimension * precision.size());
+    for value in embedded.iter().flat_map(|e| &e.embedding) {
+        match precision {
+            Precision::F32 => vectors.extend(value.to_le_bytes()),
+            Precision::F16 => vectors.extend(f16::from_f32(*value).to_le_bytes()),
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend(MAGIC);
+    header.extend(VERSION.to_le_bytes());
+    header.extend(precision.code().to_le_bytes());
+    header.extend((dimension as u32).to_le_bytes());
+    header.extend((embedded.len() as u64).to_le_bytes());
+    header.extend(Sha256::digest(&vectors));
+    header.extend((manifest.model_id.len() as u16).to_le_bytes());
+    header.extend(manifest.model_id.as_bytes());
+    header.resize(header.len().next_multiple_of(8), 0);
+
+    let chunks = embedded.iter().map(|e| &e.chunk).collect::<Vec<_>>();
+    let chunks_path = chunks_path(path);
+    let json = serde_json::to_vec(&chunks).map_err(|source| IndexError::Json {
+        path: chunks_path.clone(),
+        source,
+    })?;
+    write_aside(&chunks_path, &[&json])?;
+    let manifest_path = manifest_path(path);
+    let json = serde_json::to_vec_pretty(manifest).map_err(|source| IndexError::Json {
+        path: manifest_path.clone(),
+        source,
+    })?;
+    write_aside(&manifest_path, &[&json])?;
+    write_aside(path, &[&header, &vectors])
+}
+
+fn write_aside(path: &Path, parts: &[&[u8]]) -> Result<(), IndexError> {
+    let io_error = |source| IndexError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let temporary = path.with_extension("tmp");
+    let mut file = File::create(&temporary).map_err(io_error)?;
+    for part in parts {
+        file.write_all(part).map_err(io_error)?;
+    }
+    file.sync_all().map_err(io_error)?;
+    std::fs::rename(&temporary, path).map_err(io_error)
+}
+
+/// An index opened from disk. Its vectors stay in the memory map, paged in as searches read
+/// them.
+#[derive(Debug)]
+pub struct VectorIndex<M> {
+    mmap: Mmap,
+    offset: usize,
+    precision: Precision,
+    dimension: usize,
+    manifest: IndexManifest,
+    chunks: Vec<Chunk<M>>,
+    /// Quantized copies of the vectors to find candidates on, see
+    /// [`VectorIndex::with_quantization`].
+    quantized: Option<QuantizedSimilarity>,
+}
+
+enum Vectors<'a> {
+    F32(&'a [f32]),
+    F16(&'a [f16]),
+}
+
+impl<M: DeserializeOwned> VectorIndex<M> {
+    /// Maps the index at `path` and reads its chunks and manifest, checking the header and
+    /// checksum, and that the manifest describes these vectors and chunks.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IndexError> {
+        let path = path.as_ref();
+        let invalid = |reason: &str| IndexError::Invalid {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        };
+
+        let file = File::open(path).map_err(|source| IndexError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        // SAFETY: the map is read-only, and indexes are replaced by renaming a new file over
+        // them rather than written in place, so the mapped file does not change under us.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|source| IndexError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let bytes = &mmap[..];
+        let field = |start: usize, length: usize| {
+            bytes
+                .get(start..start + length)
+                .ok_or_else(|| invalid("truncated header"))
+        };
+        if field(0, 8)? != MAGIC {
+            return Err(invalid("not an index file"));
+        }
+        let version = u16::from_le_bytes(field(8, 2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+        let precision = Precision::from_code(u16::from_le_bytes(field(10, 2)?.try_into().unwrap()))
+            .ok_or_else(|| invalid("unknown precision"))?;
+        let dimension = u32::from_le_bytes(field(12, 4)?.try_into().unwrap()) as usize;
+        let count = u64::from_le_bytes(field(16, 8)?.try_into().unwrap()) as usize;
+        let checksum = field(24, 32)?;
+        let model_id_length = u16::from_le_bytes(field(56, 2)?.try_into().unwrap()) as usize;
+        let model_id = std::str::from_utf8(field(58, model_id_length)?)
+            .map_err(|_| invalid("model ID is not UTF-8"))?
+            .to_string();
+
+        let offset = (58 + model_id_length).next_multiple_of(8);
+        let length = count
+            .checked_mul(dimension)
+            .and_then(|values| values.checked_mul(precision.size()))
+            .ok_or_else(|| invalid("too many vectors"))?;
+        if bytes.len() != offset + length {
+            return Err(invalid(&format!(
+                "expected {} bytes of vectors, found {}",
+                length,
+                bytes.len().saturating_sub(offset)
+            )));
+        }
+        if Sha256::digest(&bytes[offset..]).as_slice() != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let chunks_path = chunks_path(path);
+        let json = std::fs::read(&chunks_path).map_err(|source| IndexError::Io {
+            path: chunks_path.clone(),
+            source,
+        })?;
+        let chunks: Vec<Chunk<M>> =
+            serde_json::from_slice(&json).map_err(|source| IndexError::Json {
+                path: chunks_path.clone(),
+                source,
+            })?;
+        if chunks.len() != count {
+            return Err(invalid(&format!(
+                "{} vectors for {} chunks",
+                count,
+                chunks.len()
+            )));
+        }
+
+        let manifest_path = manifest_path(path);
+        let json = std::fs::read(&manifest_path).map_err(|source| IndexError::Io {
+            path: manifest_path.clone(),
+            source,
+        })?;
+        let manifest: IndexManifest =
+            serde_json::from_slice(&json).map_err(|source| IndexError::Json {
+                path: manifest_path.clone(),
+                source,
+            })?;
+        if manifest.model_id != model_id || manifest.dimension != dimension {
+            return Err(invalid("the manifest is not for these vectors"));
+        }
+        if manifest.source_hash != IndexManifest::source_hash(&chunks) {
+            return Err(invalid("the manifest is not for these chunks"));
+        }
+
+        let index = Self {
+            mmap,
+            offset,
+            precision,
+            dimension,
+            manifest,
+            chunks,
+            quantized: None,
+        };
+        // The map is page aligned and the vectors start on a multiple of 8, so this only
+        // fails on exotic platforms.
+        if index.try_vectors().is_none() {
+            return Err(invalid("misaligned vectors"));
+        }
+        Ok(index)
+    }
+}
+
+impl<M> VectorIndex<M> {
+    pub fn model_id(&self) -> &str {
+        &self.manifest.model_id
+    }
+
+    pub fn manifest(&self) -> &IndexManifest {
+        &self.manifest
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn chunks(&self) -> &[Chunk<M>] {
+        &self.chunks
+    }
+
+    fn try_vectors(&self) -> Option<Vectors<'_>> {
+        let bytes = &self.mmap[self.offset..];
+        match self.precision {
+            Precision::F32 => bytemuck::try_cast_slice(bytes).ok().map(Vectors::F32),
+            Precision::F16 => bytemuck::try_cast_slice(bytes).ok().map(Vectors::F16),
+        }
+    }
+
+    fn vectors(&self) -> Vectors<'_> {
+        self.try_vectors().expect("alignment checked on open")
+    }
+
+    /// The vector of chunk `index`, widened to f32.
+    pub fn vector(&self, index: usize) -> Vec<f32> {
+        let range = index * self.dimension..(index + 1) * self.dimension;
+        match self.vectors() {
+            Vectors::F32(values) => values[range].to_vec(),
+            Vectors::F16(values) => values[range].iter().map(|v| v.to_f32()).collect(),
+        }
+    }
+
+    /// Quantizes the vectors, once, so searches find candidates on the quantized copies in
+    /// memory and only read `rescore_factor` candidates per result from the map, to rank
+    /// them at full precision.
+    pub fn with_quantization(mut self, quantization: Quantization, rescore_factor: usize) -> Self {
+        let vectors = QuantizedVectors::new(quantization, (0..self.len()).map(|i| self.vector(i)));
+        self.quantized = Some(QuantizedSimilarity::new(vectors, rescore_factor));
+        self
+    }
+
+    pub fn quantization(&self) -> Option<Quantization> {
+        self.quantized
+            .as_ref()
+            .map(|quantized| quantized.vectors().quantization())
+    }
+
+    /// The `k` chunks closest to `embedding` by cosine similarity, closest first. Fails if
+    /// the query does not have the index's dimension.
+    pub fn find_k_similar(
+        &self,
+        embedding: &[f32],
+        k: usize,
+    ) -> Result<Vec<&Chunk<M>>, IndexError> {
+        if embedding.len() != self.dimension {
+            return Err(IndexError::Incompatible {
+                reason: format!(
+                    "the query has {} dimensions instead of {}",
+                    embedding.len(),
+                    self.dimension
+                ),
+            });
+        }
+
+        if let Some(quantized) = &self.quantized {
+            let candidates = quantized
+                .vectors()
+                .candidates(embedding, k.saturating_mul(quantized.rescore_factor.max(1)));
+            let mut rescored = candidates
+                .into_iter()
+                .map(|index| {
+                    let similarity = cosine_similarity(&self.vector(index), embedding);
+                    (similarity.unwrap_or_default(), &self.chunks[index])
+                })
+                .collect::<Vec<_>>();
+            rescored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            return Ok(rescored
+                .into_iter()
+                .take(k)
+                .map(|(_, chunk)| chunk)
+                .collect());
+        }
+
+        let dimension = self.dimension.max(1);
+        let mut similarities = match self.vectors() {
+            Vectors::F32(values) => values
+                .chunks_exact(dimension)
+                .map(|vector| cosine_similarity(vector, embedding).unwrap_or_default())
+                .collect::<Vec<_>>(),
+            Vectors::F16(values) => {
+                let mut vector = vec![0.0; dimension];
+                values
+                    .chunks_exact(dimension)
+                    .map(|values| {
+                        for (widened, value) in vector.iter_mut().zip(values) {
+                            *widened = value.to_f32();
+                        }
+                        cosine_similarity(&vector, embedding).unwrap_or_default()
+                    })
+                    .collect()
+            }
+        }
+        .into_iter()
+        .zip(&self.chunks)
+        .collect::<Vec<_>>();
+
+        similarities.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(similarities
+            .into_iter()
+            .take(k)
+            .map(|(_, chunk)| chunk)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{
+        chunks_path, write, IndexError, IndexManifest, Precision, Quantization, VectorIndex,
+    };
+    use crate::{
+        document::{
+            chunker::ChunkerConfig,
+            corpus::Source,
+            local::{FileKind, LocalFileMetadata},
+            Chunk,
+        },
+        embedding::{
+            hashing::{HashingConfig, HashingEmbedder},
+            EmbeddedChunk,
+        },
+        store::Store,
+    };
+
+    fn embedded(text: &str, embedding: Vec<f32>) -> EmbeddedChunk<()> {
+        EmbeddedChunk {
+            embedding,
+            chunk: Chunk {
+                id: text.to_string(),
+                text: text.to_string(),
+                metadata: (),
+            },
+        }
+    }
+
+    fn manifest(model_id: &str, chunks: &[EmbeddedChunk<()>]) -> IndexManifest {
+        IndexManifest {
+            model_id: model_id.to_string(),
+            dimension: 3,
+            crawled_at: None,
+            crawl_sequence: None,
+            chunker: Default::default(),
+            source_hash: IndexManifest::source_hash(chunks.iter().map(|e| &e.chunk)),
+        }
+    }
+
+    #[test]
+    fn test_write_and_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.bin");
+        let chunks = vec![
+            embedded("bain", vec![1.0, 0.0, 0.0]),
+            embedded("sommeil", vec![0.0, 1.0, 0.0]),
+            embedded("boires", vec![0.6, 0.8, 0.0]),
+        ];
+        let manifest = manifest("test-model@3", &chunks);
+
+        for precision in [Precision::F32, Precision::F16] {
+            write(&path, &manifest, &chunks, precision).unwrap();
+            let index = VectorIndex::<()>::open(&path).unwrap();
+            assert_eq!(index.model_id(), "test-model@3");
+            assert_eq!(index.manifest(), &manifest);
+            assert_eq!((index.len(), index.dimension()), (3, 3));
+            assert_eq!(index.precision(), precision);
+            assert_eq!(index.vector(1), vec![0.0, 1.0, 0.0]);
+
+            let found = index.find_k_similar(&[0.0, 1.0, 0.0], 2).unwrap();
+            let texts = found.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+            assert_eq!(texts, vec!["sommeil", "boires"]);
+
+            // Quantized search rescores its candidates from the map, and ranks as exactly.
+            for quantization in [Quantization::Int8, Quantization::Binary] {
+                let index = VectorIndex::<()>::open(&path)
+                    .unwrap()
+                    .with_quantization(quantization, 2);
+                assert_eq!(index.quantization(), Some(quantization));
+                let found = index.find_k_similar(&[0.0, 1.0, 0.0], 2).unwrap();
+                let texts = found.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+                assert_eq!(texts, vec!["sommeil", "boires"]);
+            }
+        }
+        assert!(chunks_path(&path).ends_with("index.chunks.json"));
+
+        // A flipped byte in the vectors is caught by the checksum.
+        let mut bytes = std::fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        let error = VectorIndex::<()>::open(&path).unwrap_err();
+        assert!(
+            matches!(error, IndexError::Invalid { reason, .. } if reason == "checksum mismatch")
+        );
+    }
+
+    #[test]
+    fn test_manifest_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.bin");
+        let embedder = HashingEmbedder::new(HashingConfig {
+            dimension: 3,
+            ..Default::default()
+        });
+        let chunks = vec![embedded("bain", vec![1.0, 0.0, 0.0])];
+
+        // Vectors that do not have the manifest's dimension are refused.
+        let mut mismatched = chunks.clone();
+        mismatched.push(embedded("sommeil", vec![1.0, 0.0]));
+        let manifest = manifest("hashing-3-3-5", &mismatched);
+        assert!(write(&path, &manifest, &mismatched, Precision::F32).is_err());
+
+        let manifest = IndexManifest {
+            source_hash: IndexManifest::source_hash(chunks.iter().map(|e| &e.chunk)),
+            ..manifest
+        };
+        write(&path, &manifest, &chunks, Precision::F32).unwrap();
+        let index = VectorIndex::<()>::open(&path).unwrap();
+        index.manifest().check(&embedder).unwrap();
+        let other = HashingEmbedder::new(HashingConfig {
+            dimension: 3,
+            max_ngram: 4,
+            ..Default::default()
+        });
+        assert!(index.manifest().check(&other).is_err());
+        assert!(index.find_k_similar(&[1.0, 0.0], 1).is_err());
+
+        // Chunks that are not those the manifest was made for are refused.
+        std::fs::write(
+            chunks_path(&path),
+            r#"[{"id": "other", "text": "other", "metadata": null}]"#,
+        )
+        .unwrap();
+        assert!(VectorIndex::<()>::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path().join("store.db")).unwrap();
+        let chunk = |text: &str| {
+            let mut chunks = vec![Chunk::new(
+                text,
+                LocalFileMetadata {
+                    path: "bain.md".to_string(),
+                    kind: FileKind::Markdown,
+                    title: "Bain".to_string(),
+                    page: None,
+                    heading: None,
+                    headings: vec!["Bain".to_string()],
+                    heading_level: None,
+                },
+            )];
+            Chunk::assign_ids(&mut chunks);
+            chunks
+        };
+        store
+            .replace_source(&Source::LocalFile, chunk("L'eau du bain."))
+            .unwrap();
+        store
+            .record_crawl(&Source::LocalFile, &ChunkerConfig::default())
+            .unwrap();
+
+        let manifest = IndexManifest {
+            model_id: "model".to_string(),
+            dimension: 2,
+            crawled_at: store.last_crawl().unwrap().map(|crawl| crawl.crawled_at),
+            crawl_sequence: store.last_crawl().unwrap().map(|crawl| crawl.sequence),
+            chunker: BTreeMap::from([("local_file".to_string(), ChunkerConfig::default())]),
+            source_hash: IndexManifest::source_hash(store.corpus().unwrap().chunks()),
+        };
+        assert!(manifest.changes_since(&store).unwrap().is_empty());
+
+        store
+            .replace_source(&Source::LocalFile, chunk("Le coude dans l'eau."))
+            .unwrap();
+        let chunker = ChunkerConfig {
+            min_tokens: 20,
+            ..Default::default()
+        };
+        store.record_crawl(&Source::LocalFile, &chunker).unwrap();
+        // The crawl is later even within the same second.
+        let crawled_at = store.last_crawl().unwrap().unwrap().crawled_at;
+        assert_eq!(
+            manifest.changes_since(&store).unwrap(),
+            vec![
+                format!("a source was crawled again on {}", crawled_at),
+                "local_file was chunked with other settings".to_string(),
+                "the chunks in the store changed".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use super::{error::EmbeddingError, prefixed_model_id, Document, Embedder};
+use crate::http::{send_with_retry, RetryPolicy};
+
+/// An Ollama server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaConfig {
+    /// The root of the server. Requests go to `{base_url}/api/embeddings`.
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    pub model: String,
+    /// The length of the model's vectors.
+    pub dimension: usize,
+    /// How many texts to embed at once. Ollama embeds one text per request, so this is the
+    /// number of requests in flight.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Prepended to queries, for models trained with one, as `"search_query: "` for
+    /// `nomic-embed-text`.
+    #[serde(default)]
+    pub query_prefix: String,
+    /// Prepended to documents, as `"search_document: "` for `nomic-embed-text`.
+    #[serde(default)]
+    pub document_prefix: String,
+}
+
+fn default_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+fn default_batch_size() -> usize {
+    8
+}
+
+/// Embeds texts with an Ollama server.
+#[derive(Debug, Clone)]
+pub struct OllamaEmbedder {
+    client: reqwest::Client,
+    config: OllamaConfig,
+    model_id: String,
+    retry: RetryPolicy,
+}
+
+impl OllamaEmbedder {
+    pub fn new(config: OllamaConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            model_id: prefixed_model_id(
+                &config.model,
+                &config.query_prefix,
+                &config.document_prefix,
+            ),
+            config,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Retries failed requests as `retry` says.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/api/embeddings",
+            self.config.base_url.trim_end_matches('/')
+        )
+    }
+}
+
+async fn request(
+    client: &reqwest::Client,
+    url: &str,
+    model: &str,
+    text: &str,
+    retry: &RetryPolicy,
+) -> Result<Vec<f32>, EmbeddingError> {
+    let request = client.post(url).json(&EmbeddingRequest {
+        model,
+        prompt: text,
+    });
+    let response: EmbeddingResponse = send_with_retry(request, retry, None)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(response.embedding)
+}
+
+impl Embedder for OllamaEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn dimension(&self) -> usize {
+        self.config.dimension
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let text = format!("{}{}", self.config.query_prefix, text);
+        request(
+            &self.client,
+            &self.url(),
+            &self.config.model,
+            &text,
+            &self.retry,
+        )
+        .await
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let mut embeddings = vec![];
+
+        for batch in documents.chunks(self.config.batch_size.max(1)) {
+            let mut tasks = JoinSet::new();
+            for (index, document) in batch.iter().enumerate() {
+                let (client, url) = (self.client.clone(), self.url());
+                let (model, retry) = (self.config.model.clone(), self.retry.clone());
+                let text = format!("{}{}", self.config.document_prefix, document.text);
+                tasks.spawn(
+                    async move { (index, request(&client, &url, &model, &text, &retry).await) },
+                );
+            }
+
+            let mut batch_embeddings = tasks.join_all().await;
+            batch_embeddings.sort_by_key(|(index, _)| *index);
+            for (_, embedding) in batch_embeddings {
+                embeddings.push(embedding?);
+            }
+        }
+
+        Ok(embeddings)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::{OllamaConfig, OllamaEmbedder};
+    use crate::{
+        embedding::{Document, Embedder},
+        test_util::serve,
+    };
+
+    #[tokio::test]
+    async fn test_embed_batch() {
+        let app = Router::new().route(
+            "/api/embeddings",
+            post(|Json(request): Json<Value>| async move {
+                assert_eq!(request["model"], "mxbai-embed-large");
+                let length = request["prompt"].as_str().unwrap().len() as f32;
+                Json(json!({ "embedding": [length, 0.0] }))
+            }),
+        );
+        let base_url = serve(app).await;
+
+        let embedder = OllamaEmbedder::new(OllamaConfig {
+            base_url,
+            model: "mxbai-embed-large".to_string(),
+            dimension: 2,
+            batch_size: 2,
+            query_prefix: String::new(),
+            document_prefix: "d: ".to_string(),
+        });
+
+        let documents = ["a", "bb", "ccc"].map(Document::new);
+        let embeddings = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(
+            embeddings,
+            vec![vec![4.0, 0.0], vec![5.0, 0.0], vec![6.0, 0.0]]
+        );
+        assert_eq!(
+            embedder.embed_query("bonjour").await.unwrap(),
+            vec![7.0, 0.0]
+        );
+
+        let other = OllamaEmbedder::new(OllamaConfig {
+            document_prefix: "passage: ".to_string(),
+            ..embedder.config.clone()
+        });
+        assert!(embedder.model_id().starts_with("mxbai-embed-large#"));
+        assert_ne!(other.model_id(), embedder.model_id());
+    }
+}
@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+
+use super::{check_count, error::EmbeddingError, prefixed_model_id, Document, Embedder};
+use crate::http::{send_with_retry, RetryPolicy};
+
+/// A server exposing OpenAI's `/v1/embeddings` endpoint, such as vLLM, LocalAI or a
+/// text-embeddings-inference instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenAiConfig {
+    /// The root of the server. Requests go to `{base_url}/v1/embeddings`.
+    pub base_url: String,
+    pub model: String,
+    /// The length of the model's vectors.
+    pub dimension: usize,
+    /// How many texts to send per request.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// The environment variable holding the API key, for servers that need one.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Prepended to queries, for models trained with one, as `"query: "` for E5 models.
+    #[serde(default)]
+    pub query_prefix: String,
+    /// Prepended to documents, as `"passage: "` for E5 models.
+    #[serde(default)]
+    pub document_prefix: String,
+}
+
+fn default_batch_size() -> usize {
+    64
+}
+
+/// Embeds texts with an OpenAI-compatible server.
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbedder {
+    client: reqwest::Client,
+    config: OpenAiConfig,
+    model_id: String,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl OpenAiEmbedder {
+    pub fn new(config: OpenAiConfig) -> Result<Self, EmbeddingError> {
+        let api_key = config
+            .api_key_env
+            .as_ref()
+            .map(|variable| {
+                std::env::var(variable).map_err(|_| EmbeddingError::MissingKey {
+                    variable: variable.clone(),
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            model_id: prefixed_model_id(
+                &config.model,
+                &config.query_prefix,
+                &config.document_prefix,
+            ),
+            config,
+            api_key,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Retries rate-limited and failed requests as `retry` says.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    async fn request(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let url = format!(
+            "{}/v1/embeddings",
+            self.config.base_url.trim_end_matches('/')
+        );
+        let mut request = self.client.post(url).json(&EmbeddingRequest {
+            model: &self.config.model,
+            input: texts,
+        });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response: EmbeddingResponse = send_with_retry(request, &self.retry, None)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut data = response.data;
+        data.sort_by_key(|embedding| embedding.index);
+        check_count(texts.len(), data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn dimension(&self) -> usize {
+        self.config.dimension
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let text = format!("{}{}", self.config.query_prefix, text);
+        Ok(self
+            .request(&[text])
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_default())
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let mut embeddings = vec![];
+        for batch in documents.chunks(self.config.batch_size.max(1)) {
+            let texts = batch
+                .iter()
+                .map(|document| format!("{}{}", self.config.document_prefix, document.text))
+                .collect::<Vec<_>>();
+            embeddings.extend(self.request(&texts).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::{OpenAiConfig, OpenAiEmbedder};
+    use crate::{
+        embedding::{Document, Embedder},
+        test_util::serve,
+    };
+
+    /// Embeds each text as `[length]`, listing them in reverse order as the API allows,
+    /// and records the size of every request.
+    async fn embeddings(
+        State(sizes): State<Arc<Mutex<Vec<usize>>>>,
+        headers: HeaderMap,
+        Json(request): Json<Value>,
+    ) -> Json<Value> {
+        assert_eq!(headers["authorization"], "Bearer secret");
+        assert_eq!(request["model"], "nomic-embed-text");
+
+        let input = request["input"].as_array().unwrap();
+        sizes.lock().unwrap().push(input.len());
+        let data = input
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, text)| {
+                json!({ "index": index, "embedding": [text.as_str().unwrap().len() as f32] })
+            })
+            .collect::<Vec<_>>();
+        Json(json!({ "object": "list", "data": data }))
+    }
+
+    #[tokio::test]
+    async fn test_embed_batch() {
+        let sizes = Arc::new(Mutex::new(vec![]));
+        let app = Router::new()
+            .route("/v1/embeddings", post(embeddings))
+            .with_state(sizes.clone());
+        let base_url = format!("{}/", serve(app).await);
+
+        std::env::set_var("TEST_OPENAI_KEY", "secret");
+        let embedder = OpenAiEmbedder::new(OpenAiConfig {
+            base_url,
+            model: "nomic-embed-text".to_string(),
+            dimension: 1,
+            batch_size: 2,
+            api_key_env: Some("TEST_OPENAI_KEY".to_string()),
+            query_prefix: "query: ".to_string(),
+            document_prefix: String::new(),
+        })
+        .unwrap();
+
+        let documents = ["a", "bb", "ccc", "dddd", "eeeee"].map(Document::new);
+        let embeddings = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(
+            embeddings,
+            vec![vec![1.0], vec![2.0], vec![3.0], vec![4.0], vec![5.0]]
+        );
+        assert_eq!(*sizes.lock().unwrap(), vec![2, 2, 1]);
+
+        assert_eq!(embedder.embed_query("bonjour").await.unwrap(), vec![14.0]);
+
+        // Vectors made with other prefixes are told apart by the model ID.
+        assert!(embedder.model_id().starts_with("nomic-embed-text#"));
+        let unprefixed = OpenAiEmbedder::new(OpenAiConfig {
+            query_prefix: String::new(),
+            api_key_env: None,
+            ..embedder.config.clone()
+        })
+        .unwrap();
+        assert_eq!(unprefixed.model_id(), "nomic-embed-text");
+    }
+}
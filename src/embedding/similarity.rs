@@ -1,12 +1,40 @@
 use super::EmbeddedChunk;
 
 pub mod naive;
+pub mod quantized;
 
 pub trait SimilarityFinder<M> {
     fn find_k_similar<'a>(
         &self,
-        embedding: &Vec<f32>,
-        set: &'a Vec<EmbeddedChunk<M>>,
+        embedding: &[f32],
+        set: &'a [EmbeddedChunk<M>],
         k: usize,
     ) -> Vec<&'a EmbeddedChunk<M>>;
 }
+
+/// The cosine of the angle between two vectors, 0 if either is zero, or `None` if they do
+/// not have the same length and so cannot come from the same model.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let dot_product = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f32>();
+    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Some(0.0);
+    }
+    Some(dot_product / (norm_a * norm_b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cosine_similarity;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), Some(1.0));
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), Some(0.0));
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), None);
+    }
+}
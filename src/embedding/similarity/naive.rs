@@ -1,22 +1,30 @@
 use crate::embedding::EmbeddedChunk;
 
-use super::SimilarityFinder;
+use super::{cosine_similarity, SimilarityFinder};
 
 pub struct NaiveSimilarity {}
 
 impl<M> SimilarityFinder<M> for NaiveSimilarity {
     fn find_k_similar<'a>(
         &self,
-        embedding: &Vec<f32>,
-        set: &'a Vec<EmbeddedChunk<M>>,
+        embedding: &[f32],
+        set: &'a [EmbeddedChunk<M>],
         k: usize,
     ) -> Vec<&'a EmbeddedChunk<M>> {
-        let similarities = set.into_iter().map(|chunk| {
-            let similarity = cosine_similarity(&chunk.embedding, &embedding);
-            (chunk, similarity)
+        // Vectors of another length than the query's cannot be compared with it.
+        let similarities = set.iter().filter_map(|chunk| {
+            let similarity = cosine_similarity(&chunk.embedding, embedding)?;
+            Some((chunk, similarity))
         });
 
         let mut sorted = similarities.collect::<Vec<_>>();
+        if sorted.len() < set.len() {
+            tracing::warn!(
+                "Skipped {} chunks whose vectors do not have the query's {} dimensions",
+                set.len() - sorted.len(),
+                embedding.len()
+            );
+        }
         sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
 
         sorted
@@ -26,10 +34,3 @@ impl<M> SimilarityFinder<M> for NaiveSimilarity {
             .collect::<Vec<_>>()
     }
 }
-
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f32>();
-    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
-    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
-    dot_product / (norm_a * norm_b)
-}
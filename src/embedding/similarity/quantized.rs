@@ -0,0 +1,445 @@
+use std::{fmt, str::FromStr};
+
+use crate::embedding::EmbeddedChunk;
+
+use super::{cosine_similarity, naive::NaiveSimilarity, SimilarityFinder};
+
+/// How vectors are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantization {
+    /// One signed byte per dimension, scaled per vector: a quarter of the size.
+    Int8,
+    /// One bit per dimension, its sign, compared by Hamming distance: a thirty-second of the
+    /// size.
+    Binary,
+}
+
+impl fmt::Display for Quantization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantization::Int8 => write!(f, "int8"),
+            Quantization::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+impl FromStr for Quantization {
+    type Err = UnknownQuantization;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "int8" => Ok(Quantization::Int8),
+            "binary" => Ok(Quantization::Binary),
+            _ => Err(UnknownQuantization(s.to_string())),
+        }
+    }
+}
+
+/// A quantization other than `int8` or `binary`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownQuantization(pub String);
+
+impl fmt::Display for UnknownQuantization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown quantization {:?}, expected int8 or binary",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownQuantization {}
+
+#[derive(Debug, Clone)]
+enum Codes {
+    /// The codes of every vector back to back, and for each vector the factor turning the dot
+    /// product of its codes with a query into a cosine, up to the query's norm.
+    Int8 { codes: Vec<i8>, factors: Vec<f32> },
+    /// The sign bits of every vector, packed into whole words per vector.
+    Binary {
+        words: Vec<u64>,
+        words_per_vector: usize,
+    },
+}
+
+/// Quantized copies of a set of vectors, to find candidates cheaply.
+#[derive(Debug, Clone)]
+pub struct QuantizedVectors {
+    dimension: usize,
+    len: usize,
+    codes: Codes,
+}
+
+impl QuantizedVectors {
+    /// Quantizes vectors, which must all have the same dimension. They are read one at a
+    /// time, so they need not all be in memory at once.
+    pub fn new<V: AsRef<[f32]>>(
+        quantization: Quantization,
+        vectors: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let mut vectors = vectors.into_iter().peekable();
+        let dimension = vectors.peek().map_or(0, |vector| vector.as_ref().len());
+        let mut len = 0;
+
+        let codes = match quantization {
+            Quantization::Int8 => {
+                let mut codes = vec![];
+                let mut factors = vec![];
+                for vector in vectors {
+                    let vector = vector.as_ref();
+                    len += 1;
+                    let max = vector.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+                    let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                    codes.extend(vector.iter().map(|x| (x / scale).round() as i8));
+                    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                    factors.push(if norm > 0.0 { scale / norm } else { 0.0 });
+                }
+                Codes::Int8 { codes, factors }
+            }
+            Quantization::Binary => {
+                let words_per_vector = dimension.div_ceil(64);
+                let mut words = vec![];
+                for vector in vectors {
+                    len += 1;
+                    words.extend(sign_bits(vector.as_ref(), words_per_vector));
+                }
+                Codes::Binary {
+                    words,
+                    words_per_vector,
+                }
+            }
+        };
+
+        Self {
+            dimension,
+            len,
+            codes,
+        }
+    }
+
+    pub fn from_chunks<M>(quantization: Quantization, set: &[EmbeddedChunk<M>]) -> Self {
+        Self::new(quantization, set.iter().map(|e| e.embedding.as_slice()))
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        match self.codes {
+            Codes::Int8 { .. } => Quantization::Int8,
+            Codes::Binary { .. } => Quantization::Binary,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bytes taken by the codes.
+    pub fn memory(&self) -> usize {
+        match &self.codes {
+            Codes::Int8 { codes, factors } => codes.len() + factors.len() * size_of::<f32>(),
+            Codes::Binary { words, .. } => words.len() * size_of::<u64>(),
+        }
+    }
+
+    /// The indices of the `n` vectors scoring highest against `query` on their codes, best
+    /// first, or none if the query has another dimension.
+    pub fn candidates(&self, query: &[f32], n: usize) -> Vec<usize> {
+        if query.len() != self.dimension {
+            return vec![];
+        }
+
+        let mut scores = match &self.codes {
+            Codes::Int8 { codes, factors } => codes
+                .chunks_exact(self.dimension.max(1))
+                .zip(factors)
+                .map(|(codes, factor)| {
+                    let dot = codes
+                        .iter()
+                        .zip(query)
+                        .map(|(code, x)| *code as f32 * x)
+                        .sum::<f32>();
+                    dot * factor
+                })
+                .enumerate()
+                .collect::<Vec<_>>(),
+            Codes::Binary {
+                words,
+                words_per_vector,
+            } => {
+                let query = sign_bits(query, *words_per_vector);
+                words
+                    .chunks_exact((*words_per_vector).max(1))
+                    .map(|words| {
+                        let distance = words
+                            .iter()
+                            .zip(&query)
+                            .map(|(a, b)| (a ^ b).count_ones())
+                            .sum::<u32>();
+                        -(distance as f32)
+                    })
+                    .enumerate()
+                    .collect()
+            }
+        };
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores.into_iter().take(n).map(|(index, _)| index).collect()
+    }
+}
+
+/// The sign bits of a vector, set for positive values, padded with zeros to whole words.
+fn sign_bits(vector: &[f32], words_per_vector: usize) -> Vec<u64> {
+    let mut words = vec![0u64; words_per_vector];
+    for (i, x) in vector.iter().enumerate() {
+        if *x > 0.0 {
+            words[i / 64] |= 1 << (i % 64);
+        }
+    }
+    words
+}
+
+/// Finds candidates on quantized vectors, then ranks them on the full vectors.
+///
+/// The quantized vectors must have been made from the set searched, in the same order. A set
+/// of another length is searched exactly instead, with a warning.
+#[derive(Debug, Clone)]
+pub struct QuantizedSimilarity {
+    vectors: QuantizedVectors,
+    /// How many candidates per result are rescored, at least 1.
+    pub rescore_factor: usize,
+}
+
+impl QuantizedSimilarity {
+    pub fn new(vectors: QuantizedVectors, rescore_factor: usize) -> Self {
+        Self {
+            vectors,
+            rescore_factor,
+        }
+    }
+
+    pub fn vectors(&self) -> &QuantizedVectors {
+        &self.vectors
+    }
+}
+
+impl<M> SimilarityFinder<M> for QuantizedSimilarity {
+    fn find_k_similar<'a>(
+        &self,
+        embedding: &[f32],
+        set: &'a [EmbeddedChunk<M>],
+        k: usize,
+    ) -> Vec<&'a EmbeddedChunk<M>> {
+        if set.len() != self.vectors.len() {
+            tracing::warn!(
+                "Searching {} vectors with quantized copies of {}, without the copies",
+                set.len(),
+                self.vectors.len()
+            );
+            return NaiveSimilarity {}.find_k_similar(embedding, set, k);
+        }
+
+        let candidates = self
+            .vectors
+            .candidates(embedding, k.saturating_mul(self.rescore_factor.max(1)));
+        let mut rescored = candidates
+            .into_iter()
+            .filter_map(|index| {
+                let chunk = &set[index];
+                Some((chunk, cosine_similarity(&chunk.embedding, embedding)?))
+            })
+            .collect::<Vec<_>>();
+        rescored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        rescored
+            .into_iter()
+            .take(k)
+            .map(|(chunk, _)| chunk)
+            .collect()
+    }
+}
+
+/// What quantizing a set of vectors saves, and what it costs in recall against
+/// [`NaiveSimilarity`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationReport {
+    pub quantization: Quantization,
+    /// The bytes taken by the full vectors, as f32.
+    pub full_memory: usize,
+    /// The bytes taken by the quantized vectors.
+    pub quantized_memory: usize,
+    pub k: usize,
+    pub rescore_factor: usize,
+    pub queries: usize,
+    /// The share of the exact top `k` found on the quantized vectors alone, on average.
+    pub recall: f64,
+    /// The share of the exact top `k` found after rescoring the candidates.
+    pub rescored_recall: f64,
+}
+
+impl QuantizationReport {
+    /// Searches `set` for the `k` nearest neighbours of every query, exactly and on quantized
+    /// vectors, and compares the results.
+    pub fn new<M>(
+        quantization: Quantization,
+        set: &[EmbeddedChunk<M>],
+        queries: &[Vec<f32>],
+        k: usize,
+        rescore_factor: usize,
+    ) -> Self {
+        let vectors = QuantizedVectors::from_chunks(quantization, set);
+        let quantized_memory = vectors.memory();
+        let rescoring = QuantizedSimilarity::new(vectors, rescore_factor);
+        let alone = QuantizedSimilarity::new(rescoring.vectors.clone(), 1);
+
+        let (mut recall, mut rescored_recall) = (0.0, 0.0);
+        for query in queries {
+            let exact = NaiveSimilarity {}.find_k_similar(query, set, k);
+            let share = |found: Vec<&EmbeddedChunk<M>>| {
+                let hits = found
+                    .iter()
+                    .filter(|e| exact.iter().any(|x| std::ptr::eq(*x, **e)))
+                    .count();
+                hits as f64 / exact.len().max(1) as f64
+            };
+            recall += share(alone.find_k_similar(query, set, k));
+            rescored_recall += share(rescoring.find_k_similar(query, set, k));
+        }
+        let count = queries.len().max(1) as f64;
+
+        Self {
+            quantization,
+            full_memory: set
+                .iter()
+                .map(|e| e.embedding.len() * size_of::<f32>())
+                .sum(),
+            quantized_memory,
+            k,
+            rescore_factor,
+            queries: queries.len(),
+            recall: recall / count,
+            rescored_recall: rescored_recall / count,
+        }
+    }
+
+    /// The share of memory saved, from 0 to 1.
+    pub fn saved(&self) -> f64 {
+        1.0 - self.quantized_memory as f64 / self.full_memory.max(1) as f64
+    }
+}
+
+impl fmt::Display for QuantizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} bytes instead of {} ({:.1}% saved)",
+            self.quantization,
+            self.quantized_memory,
+            self.full_memory,
+            self.saved() * 100.0
+        )?;
+        writeln!(
+            f,
+            "  recall@{} over {} queries: {:.3} quantized, {:.3} rescoring {} candidates per result",
+            self.k, self.queries, self.recall, self.rescored_recall, self.rescore_factor
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Quantization, QuantizationReport, QuantizedSimilarity, QuantizedVectors};
+    use crate::{
+        document::Chunk,
+        embedding::{
+            hashing::{HashingConfig, HashingEmbedder},
+            similarity::SimilarityFinder,
+            EmbeddedChunk, Embedder,
+        },
+    };
+
+    const TEXTS: [&str; 8] = [
+        "Couchez toujours votre bébé sur le dos pour dormir.",
+        "Le lait maternel protège votre enfant contre les infections.",
+        "Vérifiez la température de l'eau du bain avec votre coude.",
+        "Les premières dents apparaissent vers six mois.",
+        "Le siège d'auto doit être orienté vers l'arrière.",
+        "La fièvre se mesure avec un thermomètre rectal.",
+        "Offrez de l'eau à votre enfant quand il fait chaud.",
+        "Le bébé dort mieux dans une pièce sombre et calme.",
+    ];
+
+    fn embedded() -> Vec<EmbeddedChunk<()>> {
+        let embedder = HashingEmbedder::new(HashingConfig {
+            dimension: 100,
+            ..Default::default()
+        });
+        TEXTS
+            .iter()
+            .map(|text| EmbeddedChunk {
+                embedding: embedder.vector(text),
+                chunk: Chunk::new(*text, ()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quantized_vectors() {
+        let set = embedded();
+        let int8 = QuantizedVectors::from_chunks(Quantization::Int8, &set);
+        assert_eq!(int8.memory(), 8 * (100 + 4));
+        let binary = QuantizedVectors::from_chunks(Quantization::Binary, &set);
+        assert_eq!(binary.quantization(), Quantization::Binary);
+        assert_eq!(binary.memory(), 8 * 2 * 8);
+
+        // Every vector is its own best candidate.
+        for (index, chunk) in set.iter().enumerate() {
+            assert_eq!(int8.candidates(&chunk.embedding, 1), vec![index]);
+            assert_eq!(binary.candidates(&chunk.embedding, 1), vec![index]);
+        }
+        assert!(int8.candidates(&[1.0], 1).is_empty());
+        assert_eq!("binary".parse(), Ok(Quantization::Binary));
+        assert!("int4".parse::<Quantization>().is_err());
+
+        let similarity = QuantizedSimilarity::new(binary, 4);
+        let found = similarity.find_k_similar(&set[2].embedding, &set, 1);
+        assert_eq!(found[0].chunk.text, TEXTS[2]);
+        // Copies of another set are not used.
+        let found = similarity.find_k_similar(&set[2].embedding, &set[..4], 1);
+        assert_eq!(found[0].chunk.text, TEXTS[2]);
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let set = embedded();
+        // Questions, not the indexed texts, whose nearest neighbours are not themselves.
+        let questions = [
+            "Comment coucher bébé?",
+            "Le lait protège-t-il des infections?",
+            "Quelle température pour le bain?",
+            "Quand sortent les dents?",
+            "Comment installer le siège d'auto?",
+        ];
+        let embedder = HashingEmbedder::new(HashingConfig {
+            dimension: 100,
+            ..Default::default()
+        });
+        let mut queries = vec![];
+        for question in questions {
+            queries.push(embedder.embed_query(question).await.unwrap());
+        }
+
+        let report = QuantizationReport::new(Quantization::Int8, &set, &queries, 3, 2);
+        assert_eq!(report.full_memory, 8 * 100 * 4);
+        assert!(report.saved() > 0.7);
+        assert!(report.recall > 0.9);
+        // Rescoring every vector finds the exact results.
+        let report = QuantizationReport::new(Quantization::Binary, &set, &queries, 3, 3);
+        assert_eq!(report.rescored_recall, 1.0);
+        assert!(report
+            .to_string()
+            .starts_with("binary: 128 bytes instead of 3200"));
+    }
+}
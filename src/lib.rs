@@ -1,3 +1,8 @@
 pub mod document;
 pub mod embedding;
+pub mod http;
 pub mod llm;
+pub mod store;
+
+#[cfg(test)]
+mod test_util;
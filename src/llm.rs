@@ -3,15 +3,17 @@ use serde::{Deserialize, Serialize};
 pub async fn chat(gemini_key: &str, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
     let gemini_client = reqwest::Client::new();
 
-    let gemini_generate_url = format!("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}", gemini_key);
+    let gemini_generate_url =
+        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent";
 
-    let gemini_request = GeminiRequest::from_prompt(&prompt);
+    let gemini_request = GeminiRequest::from_prompt(prompt);
 
     tracing::info!("Asking gemini...");
 
     let gemini_response = gemini_client
         .post(gemini_generate_url)
         .header("content-type", "application/json")
+        .header("x-goog-api-key", gemini_key)
         .json(&gemini_request)
         .send()
         .await?
@@ -40,7 +42,7 @@ impl GeminiRequest {
         GeminiRequest {
             system_instruction: GeminiSystemInstruction {
                 parts: vec![GeminiPart {
-                    text: "You are an helpful AI assistant that helps with newborn and pregnancy knowledge. Using the context provided from the mieux vivre guide, help answering the user's question. Answer in the language the question is in.".to_string(),
+                    text: "You are an helpful AI assistant that helps with newborn and pregnancy knowledge. Using the context provided, taken from the Mieux Vivre guide and the other sources named with each excerpt, help answering the user's question. Answer in the language the question is in.".to_string(),
                 }]
             },
             contents: parts,
@@ -0,0 +1,708 @@
+//! Chunks, their embeddings, the crawl state and the indexes built from them, kept together
+//! in one SQLite file shared by `crawl`, `embed` and `server`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, ToSql};
+
+use crate::{
+    document::{
+        chunker::ChunkerConfig,
+        corpus::{Corpus, Source, SourceMetadata},
+        state::{CrawlState, PageState},
+        Chunk, ChunkMetadata,
+    },
+    embedding::EmbeddedChunk,
+};
+
+/// Where the binaries keep the store unless told otherwise.
+pub const DEFAULT_PATH: &str = "bebe.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS chunks (
+        id TEXT PRIMARY KEY,
+        source TEXT NOT NULL,
+        url TEXT NOT NULL,
+        heading_path TEXT NOT NULL,
+        text TEXT NOT NULL,
+        metadata TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS chunks_source ON chunks (source);
+    CREATE INDEX IF NOT EXISTS chunks_url ON chunks (url);
+    CREATE INDEX IF NOT EXISTS chunks_heading_path ON chunks (heading_path);
+
+    CREATE TABLE IF NOT EXISTS embeddings (
+        chunk_id TEXT NOT NULL REFERENCES chunks (id) ON DELETE CASCADE,
+        model_id TEXT NOT NULL,
+        vector BLOB NOT NULL,
+        PRIMARY KEY (chunk_id, model_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS pages (
+        url TEXT PRIMARY KEY,
+        source TEXT NOT NULL,
+        etag TEXT,
+        last_modified TEXT,
+        content_hash TEXT NOT NULL,
+        fingerprint TEXT NOT NULL DEFAULT ''
+    );
+
+    CREATE TABLE IF NOT EXISTS crawls (
+        source TEXT PRIMARY KEY,
+        crawled_at TEXT NOT NULL,
+        chunker TEXT NOT NULL,
+        sequence INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS indexes (
+        version INTEGER PRIMARY KEY AUTOINCREMENT,
+        path TEXT NOT NULL,
+        model_id TEXT NOT NULL,
+        dimension INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+";
+
+/// Columns added to tables after they were first created, as `(table, column, definition)`,
+/// for stores made by older versions.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("pages", "fingerprint", "TEXT NOT NULL DEFAULT ''"),
+    ("crawls", "sequence", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/// Why the store could not be read or written.
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    /// The metadata of a stored chunk could not be serialized or parsed.
+    Json(serde_json::Error),
+    /// The path of the store or of an index could not be made absolute.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(source) => write!(f, "store query failed: {}", source),
+            StoreError::Json(source) => write!(f, "invalid chunk metadata in store: {}", source),
+            StoreError::Io(source) => write!(f, "invalid store or index path: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Sqlite(source) => Some(source),
+            StoreError::Json(source) => Some(source),
+            StoreError::Io(source) => Some(source),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self {
+        StoreError::Sqlite(error)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError::Json(error)
+    }
+}
+
+/// An index file written from the store's embeddings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexVersion {
+    pub version: i64,
+    /// Where the index is, resolved against the store's directory when recorded relative to it.
+    pub path: PathBuf,
+    pub model_id: String,
+    pub dimension: usize,
+    pub count: usize,
+    /// When it was recorded, as `YYYY-MM-DD HH:MM:SS` in UTC.
+    pub created_at: String,
+}
+
+/// The last crawl of a source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrawlRecord {
+    /// Numbers the crawls of every source in order, so a later crawl has a higher number
+    /// even within the same second.
+    pub sequence: i64,
+    /// As `YYYY-MM-DD HH:MM:SS` in UTC.
+    pub crawled_at: String,
+    pub chunker: ChunkerConfig,
+}
+
+#[derive(Debug)]
+pub struct Store {
+    connection: Connection,
+    /// The directory of the store's file, where its indexes are written.
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let dir = std::path::absolute(path.as_ref())
+            .map_err(StoreError::Io)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(SCHEMA)?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            let exists = connection
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+                    table
+                ))?
+                .exists([column])?;
+            if !exists {
+                connection.execute(
+                    &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                    [],
+                )?;
+            }
+        }
+        Ok(Self { connection, dir })
+    }
+
+    /// Every chunk, in the order they were first stored.
+    pub fn corpus(&self) -> Result<Corpus, StoreError> {
+        Ok(Corpus::new(self.query_chunks::<&str>("1", [])?))
+    }
+
+    /// The chunks of the page at `url`.
+    pub fn chunks_by_url(&self, url: &str) -> Result<Vec<Chunk<SourceMetadata>>, StoreError> {
+        self.query_chunks("url = ?1", [url])
+    }
+
+    /// The chunks under a heading path, such as `Les étapes de la grossesse > Le premier
+    /// trimestre`, including those under its subheadings.
+    pub fn chunks_by_section(
+        &self,
+        heading_path: &str,
+    ) -> Result<Vec<Chunk<SourceMetadata>>, StoreError> {
+        let nested = format!("{} > ", heading_path);
+        self.query_chunks(
+            "heading_path = ?1 OR substr(heading_path, 1, length(?2)) = ?2",
+            [heading_path, &nested],
+        )
+    }
+
+    fn query_chunks<P: ToSql>(
+        &self,
+        condition: &str,
+        values: impl IntoIterator<Item = P>,
+    ) -> Result<Vec<Chunk<SourceMetadata>>, StoreError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT id, text, metadata FROM chunks WHERE {} ORDER BY rowid",
+            condition
+        ))?;
+        let rows = statement.query_map(params_from_iter(values), |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+        })?;
+        rows.map(|row| {
+            let (id, text, metadata) = row?;
+            Ok(Chunk {
+                id,
+                text,
+                metadata: serde_json::from_str(&metadata)?,
+            })
+        })
+        .collect()
+    }
+
+    /// Inserts chunks, or updates those with the same ID. The embeddings of updated chunks are
+    /// kept, as a chunk's ID changes with its text.
+    pub fn upsert_chunks(&mut self, chunks: &[Chunk<SourceMetadata>]) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction()?;
+        upsert(&transaction, chunks)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Replaces the chunks of a source with `chunks`, deleting those that are gone along with
+    /// their embeddings. Returns how many were deleted.
+    pub fn replace_source<M: Into<SourceMetadata>>(
+        &mut self,
+        source: &Source,
+        chunks: Vec<Chunk<M>>,
+    ) -> Result<usize, StoreError> {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| Chunk {
+                id: chunk.id,
+                text: chunk.text,
+                metadata: chunk.metadata.into(),
+            })
+            .collect::<Vec<_>>();
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute("CREATE TEMP TABLE kept (id TEXT PRIMARY KEY)", [])?;
+        {
+            let mut keep = transaction.prepare("INSERT OR IGNORE INTO kept (id) VALUES (?1)")?;
+            for chunk in &chunks {
+                keep.execute([&chunk.id])?;
+            }
+        }
+        let deleted = transaction.execute(
+            "DELETE FROM chunks WHERE source = ?1 AND id NOT IN (SELECT id FROM kept)",
+            [source.key()],
+        )?;
+        transaction.execute("DROP TABLE kept", [])?;
+        upsert(&transaction, &chunks)?;
+        transaction.commit()?;
+        Ok(deleted)
+    }
+
+    /// Deletes the chunks of a removed page, with their embeddings. Returns how many there were.
+    pub fn delete_url(&mut self, url: &str) -> Result<usize, StoreError> {
+        Ok(self
+            .connection
+            .execute("DELETE FROM chunks WHERE url = ?1", [url])?)
+    }
+
+    /// What the previous crawl of a source remembered about its pages.
+    pub fn crawl_state(&self, source: &Source) -> Result<CrawlState, StoreError> {
+        let mut statement = self.connection.prepare(
+            "SELECT url, etag, last_modified, content_hash, fingerprint FROM pages WHERE source = ?1",
+        )?;
+        let pages = statement
+            .query_map([source.key()], |row| {
+                Ok((
+                    row.get(0)?,
+                    PageState {
+                        etag: row.get(1)?,
+                        last_modified: row.get(2)?,
+                        content_hash: row.get(3)?,
+                        fingerprint: row.get(4)?,
+                    },
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(CrawlState { pages })
+    }
+
+    /// The URLs of every page crawled, of any source, with chunks or not.
+    pub fn crawled_pages(&self) -> Result<BTreeSet<String>, StoreError> {
+        let mut statement = self.connection.prepare("SELECT url FROM pages")?;
+        let pages = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(pages)
+    }
+
+    /// Replaces what is remembered about the pages of a source.
+    pub fn save_crawl_state(
+        &mut self,
+        source: &Source,
+        state: &CrawlState,
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM pages WHERE source = ?1", [source.key()])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT OR REPLACE INTO pages
+                 (url, source, etag, last_modified, content_hash, fingerprint)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (url, page) in &state.pages {
+                insert.execute(params![
+                    url,
+                    source.key(),
+                    page.etag,
+                    page.last_modified,
+                    page.content_hash,
+                    page.fingerprint
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Records that a source was just crawled, and chunked with `chunker`.
+    pub fn record_crawl(
+        &mut self,
+        source: &Source,
+        chunker: &ChunkerConfig,
+    ) -> Result<(), StoreError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO crawls (source, sequence, crawled_at, chunker)
+             VALUES (?1, (SELECT coalesce(max(sequence), 0) + 1 FROM crawls), datetime('now'), ?2)",
+            params![source.key(), serde_json::to_string(chunker)?],
+        )?;
+        Ok(())
+    }
+
+    /// The last crawl of every source, by [`Source::key`].
+    pub fn crawls(&self) -> Result<BTreeMap<String, CrawlRecord>, StoreError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT source, sequence, crawled_at, chunker FROM crawls")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (source, sequence, crawled_at, chunker) = row?;
+            Ok((
+                source,
+                CrawlRecord {
+                    sequence,
+                    crawled_at,
+                    chunker: serde_json::from_str(&chunker)?,
+                },
+            ))
+        })
+        .collect()
+    }
+
+    /// The last crawl of any source.
+    pub fn last_crawl(&self) -> Result<Option<CrawlRecord>, StoreError> {
+        Ok(self
+            .crawls()?
+            .into_values()
+            .max_by(|a, b| (a.sequence, &a.crawled_at).cmp(&(b.sequence, &b.crawled_at))))
+    }
+
+    /// The chunks that have no vector from the model yet.
+    pub fn missing_embeddings(
+        &self,
+        model_id: &str,
+    ) -> Result<Vec<Chunk<SourceMetadata>>, StoreError> {
+        self.query_chunks(
+            "id NOT IN (SELECT chunk_id FROM embeddings WHERE model_id = ?1)",
+            [model_id],
+        )
+    }
+
+    /// Saves the vectors of embedded chunks, which must be in the store.
+    pub fn save_embeddings<M>(
+        &mut self,
+        model_id: &str,
+        embedded: &[EmbeddedChunk<M>],
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT OR REPLACE INTO embeddings (chunk_id, model_id, vector) VALUES (?1, ?2, ?3)",
+            )?;
+            for e in embedded {
+                let vector = e
+                    .embedding
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect::<Vec<_>>();
+                insert.execute(params![e.chunk.id, model_id, vector])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Every chunk with a vector from the model, with that vector.
+    pub fn embedded_chunks(
+        &self,
+        model_id: &str,
+    ) -> Result<Vec<EmbeddedChunk<SourceMetadata>>, StoreError> {
+        let mut statement = self.connection.prepare(
+            "SELECT chunks.id, chunks.text, chunks.metadata, embeddings.vector
+             FROM chunks JOIN embeddings ON embeddings.chunk_id = chunks.id
+             WHERE embeddings.model_id = ?1 ORDER BY chunks.rowid",
+        )?;
+        let rows = statement.query_map([model_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Vec<u8>>(3)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, text, metadata, vector) = row?;
+            Ok(EmbeddedChunk {
+                embedding: vector
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect(),
+                chunk: Chunk {
+                    id,
+                    text,
+                    metadata: serde_json::from_str(&metadata)?,
+                },
+            })
+        })
+        .collect()
+    }
+
+    /// Where to write the next version of the index: `index-<version>.bin` next to the store,
+    /// so a new index never overwrites one a server may still have open.
+    pub fn next_index_path(&self) -> Result<PathBuf, StoreError> {
+        let last: Option<i64> = self
+            .connection
+            .query_row(
+                "SELECT seq FROM sqlite_sequence WHERE name = 'indexes'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(self
+            .dir
+            .join(format!("index-{}.bin", last.unwrap_or_default() + 1)))
+    }
+
+    /// Records an index written from the store, returning its version. Paths within the
+    /// store's directory are recorded relative to it, so the store and its indexes can move
+    /// together; others are recorded absolute.
+    pub fn record_index(
+        &mut self,
+        path: impl AsRef<Path>,
+        model_id: &str,
+        dimension: usize,
+        count: usize,
+    ) -> Result<i64, StoreError> {
+        let path = std::path::absolute(path.as_ref()).map_err(StoreError::Io)?;
+        let path = path.strip_prefix(&self.dir).unwrap_or(&path);
+        self.connection.execute(
+            "INSERT INTO indexes (path, model_id, dimension, count) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                path.to_string_lossy(),
+                model_id,
+                dimension as i64,
+                count as i64
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// The index recorded last, if any.
+    pub fn latest_index(&self) -> Result<Option<IndexVersion>, StoreError> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT version, path, model_id, dimension, count, created_at FROM indexes
+                 ORDER BY version DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(IndexVersion {
+                        version: row.get(0)?,
+                        path: self.dir.join(row.get::<_, String>(1)?),
+                        model_id: row.get(2)?,
+                        dimension: row.get::<_, i64>(3)? as usize,
+                        count: row.get::<_, i64>(4)? as usize,
+                        created_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
+
+fn upsert(connection: &Connection, chunks: &[Chunk<SourceMetadata>]) -> Result<(), StoreError> {
+    let mut upsert = connection.prepare(
+        "INSERT INTO chunks (id, source, url, heading_path, text, metadata)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT (id) DO UPDATE SET source = excluded.source, url = excluded.url,
+             heading_path = excluded.heading_path, text = excluded.text,
+             metadata = excluded.metadata",
+    )?;
+    for chunk in chunks {
+        upsert.execute(params![
+            chunk.id,
+            chunk.metadata.source().key(),
+            ChunkMetadata::url(&chunk.metadata),
+            chunk.metadata.heading_path(),
+            chunk.text,
+            serde_json::to_string(&chunk.metadata)?
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::Store;
+    use crate::{
+        document::{
+            chunker::ChunkerConfig,
+            corpus::Source,
+            local::{FileKind, LocalFileMetadata},
+            state::{CrawlState, PageState},
+            Chunk,
+        },
+        embedding::EmbeddedChunk,
+    };
+
+    fn chunks(path: &str, texts: &[(&str, &str)]) -> Vec<Chunk<LocalFileMetadata>> {
+        let mut chunks = texts
+            .iter()
+            .map(|(heading, text)| {
+                Chunk::new(
+                    *text,
+                    LocalFileMetadata {
+                        path: path.to_string(),
+                        kind: FileKind::Markdown,
+                        title: "Guide".to_string(),
+                        page: None,
+                        heading: Some(heading.to_string()),
+                        headings: vec!["Guide".to_string(), heading.to_string()],
+                        heading_level: Some(2),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        Chunk::assign_ids(&mut chunks);
+        chunks
+    }
+
+    #[test]
+    fn test_chunks_and_embeddings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path().join("store.db")).unwrap();
+
+        let first = [
+            chunks(
+                "bain.md",
+                &[("Bain", "L'eau du bain."), ("Eau", "Le coude.")],
+            ),
+            chunks("sommeil.md", &[("Sommeil", "Sur le dos.")]),
+        ]
+        .concat();
+        assert_eq!(store.replace_source(&Source::LocalFile, first).unwrap(), 0);
+        let embedded = store
+            .corpus()
+            .unwrap()
+            .into_chunks()
+            .into_iter()
+            .map(|chunk| EmbeddedChunk {
+                embedding: vec![chunk.text.len() as f32, 0.5],
+                chunk,
+            })
+            .collect::<Vec<_>>();
+        store.save_embeddings("model", &embedded).unwrap();
+        assert!(store.missing_embeddings("model").unwrap().is_empty());
+        assert_eq!(store.missing_embeddings("other").unwrap().len(), 3);
+
+        // The sleep page is removed and the bath page gains a chunk: only that one needs a
+        // vector, and the removed page's vector is gone with it.
+        let second = chunks(
+            "bain.md",
+            &[
+                ("Bain", "L'eau du bain."),
+                ("Eau", "Le coude."),
+                ("Eau", "Tiède."),
+            ],
+        );
+        assert_eq!(store.replace_source(&Source::LocalFile, second).unwrap(), 1);
+        let missing = store.missing_embeddings("model").unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].text, "Tiède.");
+        let embedded = store.embedded_chunks("model").unwrap();
+        assert_eq!(embedded.len(), 2);
+        assert_eq!(embedded[0].embedding, vec![14.0, 0.5]);
+
+        assert_eq!(store.chunks_by_url("bain.md").unwrap().len(), 3);
+        assert_eq!(store.chunks_by_section("Guide > Eau").unwrap().len(), 2);
+        assert_eq!(store.chunks_by_section("Guide").unwrap().len(), 3);
+        assert_eq!(store.chunks_by_section("Guide > E").unwrap().len(), 0);
+        assert_eq!(store.delete_url("bain.md").unwrap(), 3);
+        assert!(store.corpus().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_crawl_state_and_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.db");
+        let mut store = Store::open(&path).unwrap();
+
+        let mut state = CrawlState::default();
+        state.pages.insert(
+            "https://example.com/bain".to_string(),
+            PageState {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                content_hash: "abc".to_string(),
+                fingerprint: "def".to_string(),
+            },
+        );
+        store.save_crawl_state(&Source::MieuxVivre, &state).unwrap();
+        store
+            .record_crawl(&Source::MieuxVivre, &ChunkerConfig::default())
+            .unwrap();
+        assert!(store.latest_index().unwrap().is_none());
+        let first = store.next_index_path().unwrap();
+        assert_eq!(first, dir.path().join("index-1.bin"));
+        store.record_index(&first, "model", 2, 10).unwrap();
+        let second = store.next_index_path().unwrap();
+        let version = store.record_index(&second, "model", 2, 12).unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let other = elsewhere.path().join("index.bin");
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.crawl_state(&Source::MieuxVivre).unwrap(), state);
+        assert_eq!(
+            store.crawled_pages().unwrap(),
+            BTreeSet::from(["https://example.com/bain".to_string()])
+        );
+        assert!(store
+            .crawl_state(&Source::LocalFile)
+            .unwrap()
+            .pages
+            .is_empty());
+        let latest = store.latest_index().unwrap().unwrap();
+        assert_eq!(latest.version, version);
+        assert_eq!(latest.path, dir.path().join("index-2.bin"));
+        assert_eq!(latest.count, 12);
+
+        // The store and its indexes can move together.
+        let moved = tempfile::tempdir().unwrap();
+        drop(store);
+        std::fs::rename(&path, moved.path().join("store.db")).unwrap();
+        let mut store = Store::open(moved.path().join("store.db")).unwrap();
+        let latest = store.latest_index().unwrap().unwrap();
+        assert_eq!(latest.path, moved.path().join("index-2.bin"));
+
+        // Indexes elsewhere are recorded where they are.
+        store.record_index(&other, "model", 2, 12).unwrap();
+        assert_eq!(store.latest_index().unwrap().unwrap().path, other);
+        assert_eq!(
+            store.next_index_path().unwrap(),
+            moved.path().join("index-4.bin")
+        );
+    }
+
+    #[test]
+    fn test_open_older_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.db");
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE pages (url TEXT PRIMARY KEY, source TEXT NOT NULL, etag TEXT,
+                 last_modified TEXT, content_hash TEXT NOT NULL);
+                 INSERT INTO pages VALUES ('https://example.com', 'mieux_vivre', NULL, NULL, 'abc');",
+            )
+            .unwrap();
+
+        let store = Store::open(&path).unwrap();
+        let state = store.crawl_state(&Source::MieuxVivre).unwrap();
+        assert_eq!(state.pages["https://example.com"].fingerprint, "");
+    }
+}
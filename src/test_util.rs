@@ -0,0 +1,96 @@
+//! Stand-ins shared by the tests of several modules.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::embedding::{
+    error::EmbeddingError,
+    hashing::{HashingConfig, HashingEmbedder},
+    Document, Embedder,
+};
+
+/// Serves `app` on a free local port for the rest of the test, and returns its base URL, as
+/// in "http://127.0.0.1:1234".
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    base_url
+}
+
+/// A hashing embedder counting the texts and batches it embeds, which can be made to fail
+/// after a number of batches.
+pub struct TestEmbedder {
+    inner: HashingEmbedder,
+    /// The texts embedded so far, queries included.
+    texts: AtomicUsize,
+    /// The non-empty batches of documents embedded, or attempted, so far.
+    batches: AtomicUsize,
+    fail_after: Option<usize>,
+    /// Whether to leave the last vector of each batch out, as a faulty server would.
+    short: bool,
+}
+
+impl TestEmbedder {
+    pub fn new() -> Self {
+        Self {
+            inner: HashingEmbedder::new(HashingConfig::default()),
+            texts: AtomicUsize::new(0),
+            batches: AtomicUsize::new(0),
+            fail_after: None,
+            short: false,
+        }
+    }
+
+    /// Answers every batch with one vector too few.
+    pub fn short(mut self) -> Self {
+        self.short = true;
+        self
+    }
+
+    /// Fails every batch after the first `limit` ones.
+    pub fn failing_after(mut self, limit: usize) -> Self {
+        self.fail_after = Some(limit);
+        self
+    }
+
+    pub fn texts(&self) -> usize {
+        self.texts.load(Ordering::SeqCst)
+    }
+}
+
+impl Embedder for TestEmbedder {
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        self.texts.fetch_add(1, Ordering::SeqCst);
+        self.inner.embed_query(text).await
+    }
+
+    async fn embed_documents(
+        &self,
+        documents: &[Document<'_>],
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        if documents.is_empty() {
+            return Ok(vec![]);
+        }
+        let batch = self.batches.fetch_add(1, Ordering::SeqCst);
+        if self.fail_after.is_some_and(|limit| batch >= limit) {
+            return Err(EmbeddingError::Count {
+                expected: documents.len(),
+                got: 0,
+            });
+        }
+        self.texts.fetch_add(documents.len(), Ordering::SeqCst);
+        let mut vectors = self.inner.embed_documents(documents).await?;
+        if self.short {
+            vectors.pop();
+        }
+        Ok(vectors)
+    }
+}